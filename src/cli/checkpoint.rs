@@ -0,0 +1,227 @@
+use alloy::primitives::{Address, U256};
+use eyre::{Context, Result};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A task event that has been processed after the checkpoint block.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CheckpointTask {
+    /// Block number of the event.
+    pub block_number: u64,
+    /// Task id of the event.
+    pub task_id: U256,
+    /// The `statusAfter` field of the event.
+    pub status: u8,
+}
+
+/// The checkpoint that is stored on disk.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Checkpoint {
+    /// All events until and including this block are processed.
+    pub block_number: u64,
+    /// Events that are processed after `block_number`, so that they are not processed again.
+    pub tasks: Vec<CheckpointTask>,
+}
+
+/// A local checkpoint of the processed coordinator events, stored as a JSON file
+/// within the data directory.
+///
+/// As tasks are processed concurrently, a block is only considered processed when
+/// all the events within it and the blocks before it are processed. Blocks without
+/// any events are processed as soon as they are synced, see [`CheckpointStore::sync`].
+pub struct CheckpointStore {
+    /// Path to the checkpoint file.
+    path: PathBuf,
+    /// Current checkpoint, `None` if there is no checkpoint yet.
+    checkpoint: Option<Checkpoint>,
+    /// Number of events that are not processed yet, per block.
+    pending: BTreeMap<u64, usize>,
+    /// The highest block number among the events & synced blocks seen so far.
+    latest_block: u64,
+}
+
+impl CheckpointStore {
+    /// Opens the checkpoint for the given chain & coordinator within the data directory,
+    /// creating the directory if it does not exist.
+    pub fn open(data_dir: &Path, chain_id: u64, coordinator: Address) -> Result<Self> {
+        fs::create_dir_all(data_dir).wrap_err("could not create data directory")?;
+        let path = data_dir.join(format!("checkpoint-{}-{}.json", chain_id, coordinator));
+
+        let checkpoint = if path.exists() {
+            let content = fs::read_to_string(&path).wrap_err("could not read checkpoint")?;
+            let checkpoint: Checkpoint =
+                serde_json::from_str(&content).wrap_err("could not parse checkpoint")?;
+            Some(checkpoint)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            path,
+            latest_block: checkpoint
+                .as_ref()
+                .map(|c| c.block_number)
+                .unwrap_or_default(),
+            checkpoint,
+            pending: BTreeMap::new(),
+        })
+    }
+
+    /// Deletes the existing checkpoint, if any.
+    pub fn reset(&mut self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path).wrap_err("could not remove checkpoint")?;
+        }
+        self.checkpoint = None;
+        self.latest_block = 0;
+
+        Ok(())
+    }
+
+    /// Returns the last fully processed block, if there is a checkpoint.
+    pub fn block_number(&self) -> Option<u64> {
+        self.checkpoint.as_ref().map(|c| c.block_number)
+    }
+
+    /// Returns `true` if the event is processed after the checkpoint block.
+    ///
+    /// Events before the checkpoint block are not considered here, so that
+    /// they can be processed again when an earlier block is given explicitly.
+    pub fn is_processed(&self, block_number: u64, task_id: U256, status: u8) -> bool {
+        self.checkpoint.as_ref().is_some_and(|checkpoint| {
+            checkpoint.tasks.iter().any(|t| {
+                t.block_number == block_number && t.task_id == task_id && t.status == status
+            })
+        })
+    }
+
+    /// Marks an event at the given block as pending, i.e. it is queued for processing.
+    pub fn start(&mut self, block_number: u64) {
+        *self.pending.entry(block_number).or_default() += 1;
+        self.latest_block = self.latest_block.max(block_number);
+    }
+
+    /// Marks an event as processed, and saves the checkpoint to disk.
+    pub fn finish(&mut self, block_number: u64, task_id: U256, status: u8) -> Result<()> {
+        if let Some(count) = self.pending.get_mut(&block_number) {
+            *count -= 1;
+            if *count == 0 {
+                self.pending.remove(&block_number);
+            }
+        }
+
+        let checkpoint = self.checkpoint.get_or_insert_with(Checkpoint::default);
+        checkpoint.tasks.push(CheckpointTask {
+            block_number,
+            task_id,
+            status,
+        });
+        self.advance();
+
+        self.save()
+    }
+
+    /// Marks all events until and including the given block as seen, e.g. the block is polled
+    /// without any events; and saves the checkpoint to disk if it has advanced.
+    pub fn sync(&mut self, block_number: u64) -> Result<()> {
+        if block_number <= self.latest_block {
+            return Ok(());
+        }
+        self.latest_block = block_number;
+
+        if self.advance() {
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    /// Advances the checkpoint block w.r.t the pending events, returns `true` if it has advanced.
+    fn advance(&mut self) -> bool {
+        // all blocks before the first pending block are processed, and if there
+        // are no pending blocks, all blocks up to the latest one are processed
+        let processed_block = match self.pending.keys().next() {
+            Some(first_pending) => first_pending.saturating_sub(1),
+            None => self.latest_block,
+        };
+        let checkpoint = self.checkpoint.get_or_insert_with(Checkpoint::default);
+        let advanced = processed_block > checkpoint.block_number;
+        if advanced {
+            checkpoint.block_number = processed_block;
+        }
+        let block_number = checkpoint.block_number;
+        checkpoint.tasks.retain(|t| t.block_number > block_number);
+
+        advanced
+    }
+
+    /// Writes the checkpoint to disk, first to a temporary file and then renaming it
+    /// so that a crash does not leave a corrupted checkpoint behind.
+    fn save(&self) -> Result<()> {
+        let Some(checkpoint) = &self.checkpoint else {
+            return Ok(());
+        };
+
+        let content =
+            serde_json::to_string_pretty(checkpoint).wrap_err("could not serialize checkpoint")?;
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).wrap_err("could not write checkpoint")?;
+        fs::rename(&tmp_path, &self.path).wrap_err("could not save checkpoint")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_store() -> Result<()> {
+        let data_dir = std::env::temp_dir().join(format!(
+            "dria-oracle-checkpoint-test-{}",
+            rand::random::<u64>()
+        ));
+        let mut store = CheckpointStore::open(&data_dir, 31337, Address::ZERO)?;
+        assert_eq!(store.block_number(), None);
+
+        // two tasks at block 10, one at block 12
+        store.start(10);
+        store.start(10);
+        store.start(12);
+
+        // finishing the task at 12 should not move the checkpoint beyond 10
+        store.finish(12, U256::from(3), 1)?;
+        assert_eq!(store.block_number(), Some(9));
+        assert!(store.is_processed(12, U256::from(3), 1));
+        assert!(!store.is_processed(10, U256::from(1), 1));
+
+        // finishing both tasks at 10 should move the checkpoint to 12
+        store.finish(10, U256::from(1), 1)?;
+        assert_eq!(store.block_number(), Some(9));
+        store.finish(10, U256::from(2), 1)?;
+        assert_eq!(store.block_number(), Some(12));
+
+        assert!(!store.is_processed(12, U256::from(3), 1));
+
+        // should be persisted
+        let mut store = CheckpointStore::open(&data_dir, 31337, Address::ZERO)?;
+        assert_eq!(store.block_number(), Some(12));
+
+        // blocks without events advance the checkpoint once the pending events before them are processed
+        store.start(14);
+        store.sync(20)?;
+        assert_eq!(store.block_number(), Some(13));
+        store.finish(14, U256::from(4), 1)?;
+        assert_eq!(store.block_number(), Some(20));
+        store.sync(25)?;
+        let store = CheckpointStore::open(&data_dir, 31337, Address::ZERO)?;
+        assert_eq!(store.block_number(), Some(25));
+
+        fs::remove_dir_all(&data_dir)?;
+        Ok(())
+    }
+}
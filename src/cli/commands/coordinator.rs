@@ -1,217 +1,1213 @@
-use std::time::Duration;
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeSet, HashMap},
+    future::Future,
+    path::PathBuf,
+    pin::pin,
+    rc::Rc,
+    time::Duration,
+};
 
 use crate::{
-    compute::handle_request,
+    cli::checkpoint::CheckpointStore,
+    cli::control::{task_kind, ControlMessage, ControlRequest},
+    cli::health::{Check, HealthState},
+    cli::identity::{Identities, Identity},
+    cli::queue::{log_key, LogKey, TaskQueue},
+    cli::retry::{is_retryable_error, RetryQueue},
+    compute::{handle_request, ProfitabilityGate, ProtocolFilter},
     contracts::{bytes_to_string, string_to_bytes, OracleKind, TaskStatus},
-    DriaOracle,
+    node::is_fee_ceiling_error,
+    telemetry, DriaOracle, Journal,
     OracleCoordinator::StatusUpdate,
 };
 use alloy::{
     eips::BlockNumberOrTag,
-    primitives::{utils::format_ether, U256},
+    network::EthereumWallet,
+    primitives::{utils::format_ether, B256, U256},
+    providers::Provider,
     rpc::types::Log,
 };
 use dkn_workflows::{DriaWorkflowsConfig, Model, ModelProvider};
 use eyre::{eyre, Context, Result};
-use futures_util::StreamExt;
+use futures_util::{
+    future::{FutureExt, LocalBoxFuture},
+    stream::FuturesUnordered,
+    StreamExt, TryStreamExt,
+};
+use serde_json::{json, Value};
+use tokio::{sync::mpsc, time::Instant};
 use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+/// Options for the event loop of the oracle node.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Maximum number of tasks to be processed concurrently.
+    pub max_tasks: usize,
+    /// Timeout for a single task, the task is dropped if it takes longer than this.
+    pub task_timeout: Duration,
+    /// Directory to store local data such as the checkpoint.
+    pub data_dir: PathBuf,
+    /// Whether to ignore & delete the existing checkpoint.
+    pub reset_checkpoint: bool,
+    /// Number of blocks to wait on top of an event's block before processing its task.
+    pub confirmations: u64,
+    /// Maximum number of retries for a task that failed with a retryable error.
+    pub max_retries: u32,
+    /// Delay before the first retry of a task, doubled for each subsequent retry.
+    pub retry_delay: Duration,
+    /// Time to wait for the tasks in progress to finish when shutting down.
+    pub grace_period: Duration,
+    /// Filter for the protocols of the tasks to be served.
+    pub protocols: ProtocolFilter,
+    /// Optional gate to skip the tasks that are not profitable.
+    pub profitability: Option<ProfitabilityGate>,
+    /// Optional health state to record the readiness checks & event stream polls to.
+    pub health: Option<HealthState>,
+    /// Optional journal to record the handled tasks to.
+    pub journal: Option<Journal>,
+    /// Wallets of the other identities to serve tasks as, alongside the wallet of the node.
+    pub identities: Vec<EthereumWallet>,
+}
+
+/// Workflows config of the served models, shared by the event loops of all chains & their tasks.
+///
+/// The models can be replaced by the control API, where the tasks in progress keep the previous config.
+pub type SharedModelConfig = RefCell<Rc<DriaWorkflowsConfig>>;
+
+/// Tasks that are being processed, with their events, cancellation tokens and whether their
+/// responses are being submitted.
+type InProgress = HashMap<LogKey, (StatusUpdate, CancellationToken, Rc<Cell<bool>>)>;
+
+/// Interval to check the confirmations of the queued tasks.
+const CONFIRMATION_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Interval to run the readiness checks, if there is a health state.
+const READINESS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Delay before trying a task again, when it is deferred due to the fee ceiling.
+const FEE_DEFER_DELAY: Duration = Duration::from_secs(30);
+
+/// Timeout of the lookups for the confirmations & retries.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Results of the RPC lookups that are made alongside the event loop.
+enum Lookup {
+    /// The head block, if it is known, and the hashes of the confirmed blocks.
+    Confirmations(Option<u64>, HashMap<u64, Result<Option<B256>>>),
+    /// On-chain statuses of the tasks that are due for a retry.
+    Retries(Vec<(LogKey, Result<u8>)>),
+}
 
 impl DriaOracle {
     /// Runs the main loop of the oracle node.
+    ///
+    /// Events are read from the coordinator and queued, and at most `options.max_tasks`
+    /// of them are processed at the same time while the events are still being read.
+    ///
+    /// If `from_block` is not given, the node resumes from its local checkpoint if there is one,
+    /// otherwise it starts from the latest block.
+    ///
+    /// The node serves tasks as its own wallet & the identities of `options`, each with the given
+    /// kinds or its own registrations if no kinds are given; see [`Identities::choose`] for which
+    /// identity handles a task. In dry-run mode, the registrations of the given kinds are not required.
+    ///
+    /// The models are shared with the event loops of the other chains, if there are any,
+    /// see [`DriaOracle::prepare_models`]. Requests of the control API are received from
+    /// `control` if given, and handled within the loop, see [`ControlRequest`].
     pub(in crate::cli) async fn run_oracle(
         &self,
-        mut kinds: Vec<OracleKind>,
-        models: Vec<Model>,
-        from_block: BlockNumberOrTag,
+        kinds: Vec<OracleKind>,
+        model_config: &SharedModelConfig,
+        from_block: Option<BlockNumberOrTag>,
+        options: RunOptions,
+        mut control: Option<mpsc::Receiver<ControlMessage>>,
         cancellation: CancellationToken,
     ) -> Result<()> {
-        // if kinds are not provided, use the registrations as kinds
-        if kinds.is_empty() {
-            log::debug!("No kinds provided. Checking registrations.");
-            for kind in [OracleKind::Generator, OracleKind::Validator] {
-                if self.is_registered(kind).await? {
-                    kinds.push(kind);
-                }
-            }
-
-            if kinds.is_empty() {
-                return Err(eyre!("You are not registered as any type of oracle."))?;
-            }
-        } else {
-            // otherwise, make sure we are registered to required kinds
-            for kind in &kinds {
-                if !self.is_registered(*kind).await? {
-                    return Err(eyre!("You need to register as {} first.", kind))?;
-                }
-            }
+        if options.max_tasks == 0 {
+            return Err(eyre!("Maximum number of tasks must be at least 1."));
+        }
+        if !kinds.is_empty() && self.config.dry_run {
+            log::warn!("Dry run, not checking the registrations.");
         }
-        log::info!(
-            "Running as: {}",
-            kinds
-                .iter()
-                .map(|kind| kind.to_string())
-                .collect::<Vec<String>>()
-                .join(", ")
-        );
 
-        // prepare model config & check services
-        let mut model_config = DriaWorkflowsConfig::new(models);
-        if model_config.models.is_empty() {
-            return Err(eyre!("No models provided."))?;
+        // each identity serves the given kinds, or the kinds it is registered as
+        let mut identities =
+            vec![Identity::new(self.connect(self.config.wallet.clone()), &kinds).await?];
+        for wallet in &options.identities {
+            identities.push(Identity::new(self.connect(wallet.clone()), &kinds).await?);
+        }
+        let identities = Identities::new(identities);
+        for identity in identities.iter() {
+            log::info!(
+                "Running {} as: {}",
+                identity.node.address(),
+                identity
+                    .kinds
+                    .iter()
+                    .map(|kind| kind.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            );
+        }
+        let kinds = identities.kinds();
+        Self::check_validator_models(&kinds, &model_config.borrow())?;
+        if !options.protocols.is_empty() {
+            log::info!("Serving protocols with {}", options.protocols);
+        }
+        if self.config.dry_run {
+            log::warn!("Running in dry-run mode, no uploads or transactions will be made.");
         }
-        let ollama_config = model_config.ollama.clone();
-        model_config = model_config.with_ollama_config(
-            ollama_config
-                .with_min_tps(5.0)
-                .with_timeout(Duration::from_secs(150)),
-        );
-        model_config.check_services().await?;
 
-        // validator-specific checks here
-        if kinds.contains(&OracleKind::Validator) {
-            // make sure we have GPT4o model
-            if !model_config
-                .models
-                .contains(&(ModelProvider::OpenAI, Model::GPT4o))
-            {
-                return Err(eyre!("Validator must have GPT4o model."))?;
+        // open the local checkpoint, and decide where to start from
+        let mut checkpoint =
+            CheckpointStore::open(&options.data_dir, self.chain_id, self.addresses.coordinator)
+                .wrap_err("could not open checkpoint")?;
+        if options.reset_checkpoint {
+            log::warn!("Resetting the checkpoint.");
+            checkpoint.reset()?;
+        }
+        let from_block = match (from_block, checkpoint.block_number()) {
+            (Some(from_block), _) => from_block,
+            (None, Some(block_number)) => {
+                log::info!("Resuming from checkpoint at block {}.", block_number);
+                BlockNumberOrTag::Number(block_number + 1)
             }
+            (None, None) => BlockNumberOrTag::Latest,
+        };
+
+        // tasks that are waiting for confirmations or a free worker
+        let mut queue = TaskQueue::new(options.confirmations);
+        // tasks that are being processed at the moment, and their cancellation tokens
+        let mut workers = FuturesUnordered::new();
+        let mut in_progress = InProgress::new();
+        // tasks that have failed and are waiting to be retried
+        let mut retries = RetryQueue::new(options.max_retries, options.retry_delay);
+        // interval to check for confirmations of the queued tasks
+        let mut confirmation_interval = tokio::time::interval(CONFIRMATION_INTERVAL);
+        // kinds of tasks that are paused by the control API, their tasks are kept in the queue
+        let mut paused: Vec<OracleKind> = Vec::new();
+        // control requests that take a while, such as claims, so that they do not block the loop
+        let mut control_jobs: FuturesUnordered<LocalBoxFuture<'_, ()>> = FuturesUnordered::new();
+        // lookups of the confirmations & retries, at most one of each at a time, so that they do not block the loop
+        let mut lookups: FuturesUnordered<LocalBoxFuture<'_, Lookup>> = FuturesUnordered::new();
+        let (mut confirming, mut retrying) = (false, false);
 
-            // make sure node is whitelisted
-            if !self.is_whitelisted(self.address()).await? {
-                return Err(eyre!("You are not whitelisted in the registry."))?;
+        // readiness checks run alongside the event loop, if they are enabled
+        let readiness = async {
+            match &options.health {
+                Some(health) => {
+                    self.check_readiness(&identities, model_config, health)
+                        .await
+                }
+                None => std::future::pending().await,
             }
-        }
+        };
+        let mut readiness = pin!(readiness);
+
+        // the block until which the events are known, so that the gap
+        // between a previous stream (or backfill) and a new stream can be filled
+        let mut last_block: Option<u64> = None;
 
         // check previous tasks if `from_block` is not `Latest`
         if from_block != BlockNumberOrTag::Latest {
+            let head = self.provider.get_block_number().await?;
             tokio::select! {
                 _ = cancellation.cancelled() => {
                     log::debug!("Cancellation signal received. Stopping...");
                     return Ok(());
                 }
-                result = self.handle_previous_tasks(from_block, &model_config, &kinds) => {
-                    if let Err(e) = result {
-                        log::error!("Could not handle previous tasks: {:?}", e);
-                        log::warn!("Continuing anyways...");
+                result = self.handle_previous_tasks(from_block, head, &mut queue, &mut checkpoint) => {
+                    match result {
+                        Ok(_) => Self::sync_checkpoint(&mut checkpoint, head),
+                        Err(e) => {
+                            log::error!("Could not handle previous tasks: {:?}", e);
+                            log::warn!("Continuing anyways...");
+                        }
                     }
                 }
             }
+            last_block = Some(head);
         }
 
-        loop {
+        'events: loop {
             // subscribe to new tasks
             log::info!(
                 "Subscribing to LLMOracleCoordinator ({})",
                 self.addresses.coordinator,
             );
-            let mut event_stream = self
-                .subscribe_to_tasks()
-                .await
-                .wrap_err("could not subscribe to tasks")?
-                .into_stream();
+            let mut event_stream = match self.subscribe_to_tasks().await {
+                Ok(event_stream) => {
+                    if let Some(health) = &options.health {
+                        health.record_poll();
+                    }
+                    event_stream
+                }
+                Err(e) => {
+                    log::error!("Could not subscribe to tasks: {:?}", e);
+                    log::warn!("Waiting a bit before retrying.");
+                    let wait = Duration::from_secs(5);
+                    if !Self::wait_with_workers(
+                        &mut workers,
+                        &mut in_progress,
+                        &mut retries,
+                        &mut checkpoint,
+                        wait,
+                        &cancellation,
+                    )
+                    .await
+                    {
+                        break 'events;
+                    }
+                    continue;
+                }
+            };
+
+            // fill the gap between the previous stream and the new one,
+            // events that are seen by both are de-duplicated within the queue
+            match self.provider.get_block_number().await {
+                Ok(head) => match last_block {
+                    Some(from_block) if from_block < head => {
+                        tokio::select! {
+                            _ = cancellation.cancelled() => {
+                                log::debug!("Cancellation signal received. Stopping...");
+                                break 'events;
+                            }
+                            result = self.handle_previous_tasks(from_block.into(), head, &mut queue, &mut checkpoint) => {
+                                match result {
+                                    Ok(_) => {
+                                        last_block = Some(head);
+                                        Self::sync_checkpoint(&mut checkpoint, head);
+                                    }
+                                    Err(e) => log::error!("Could not fill the event gap: {:?}", e),
+                                }
+                            }
+                        }
+                    }
+                    Some(_) => {}
+                    None => last_block = Some(head),
+                },
+                Err(e) => log::error!("Could not get block number: {:?}", e),
+            }
 
             // start the event loop
             log::info!("Listening for events...");
             loop {
+                // fill the free workers with queued tasks, except the paused ones
+                while workers.len() < options.max_tasks {
+                    let Some((event, log)) = queue.pop_where(
+                        |event| !matches!(task_kind(event), Some(kind) if paused.contains(&kind)),
+                    ) else {
+                        break;
+                    };
+                    let task_cancellation = CancellationToken::new();
+                    let submitted = Rc::new(Cell::new(false));
+                    in_progress.insert(
+                        log_key(&log),
+                        (event.clone(), task_cancellation.clone(), submitted.clone()),
+                    );
+                    let span = telemetry::task_span(self.chain_id, &event);
+                    let task_model_config = model_config.borrow().clone();
+                    workers.push(
+                        self.handle_event_log(
+                            event,
+                            log,
+                            &identities,
+                            task_model_config,
+                            &options,
+                            task_cancellation,
+                            submitted,
+                        )
+                        .instrument(span),
+                    );
+                }
+
                 tokio::select! {
                     _ = cancellation.cancelled() => {
                         log::debug!("Cancellation signal received. Stopping...");
-                        return Ok(());
+                        break 'events;
+                    }
+                    Some((event, log, result)) = workers.next(), if !workers.is_empty() => {
+                        // a worker is free now, it will be filled in the next iteration
+                        Self::finish_task(
+                            event,
+                            log,
+                            result,
+                            &mut in_progress,
+                            &mut retries,
+                            &mut checkpoint,
+                        );
+                    }
+                    _ = confirmation_interval.tick(), if queue.has_unconfirmed() && !confirming => {
+                        confirming = true;
+                        lookups.push(
+                            self.lookup_confirmations(queue.unconfirmed_blocks(), queue.confirmations())
+                                .boxed_local(),
+                        );
+                    }
+                    _ = tokio::time::sleep_until(retries.next_due().unwrap_or_else(Instant::now)), if !retries.is_empty() && !retrying => {
+                        retrying = true;
+                        lookups.push(self.lookup_retries(retries.due(Instant::now())).boxed_local());
+                    }
+                    Some(lookup) = lookups.next(), if !lookups.is_empty() => match lookup {
+                        Lookup::Confirmations(head, block_hashes) => {
+                            confirming = false;
+                            Self::confirm_tasks(head, block_hashes, &mut queue, &mut checkpoint);
+                        }
+                        Lookup::Retries(statuses) => {
+                            retrying = false;
+                            Self::retry_tasks(statuses, &mut queue, &mut retries, &mut checkpoint);
+                        }
+                    },
+                    _ = &mut readiness => {}
+                    Some(()) = control_jobs.next(), if !control_jobs.is_empty() => {}
+                    Some((request, reply)) = Self::next_control(&mut control) => {
+                        let response = match request {
+                            ControlRequest::Pause(kind) if !kinds.contains(&kind) => {
+                                Err(format!("Not serving {} tasks.", kind))
+                            }
+                            ControlRequest::Pause(kind) => {
+                                if !paused.contains(&kind) {
+                                    log::warn!("Pausing the intake of {} tasks.", kind);
+                                    paused.push(kind);
+                                }
+                                Ok(json!({ "paused": Self::kind_names(&paused) }))
+                            }
+                            ControlRequest::Resume(kind) => {
+                                if paused.contains(&kind) {
+                                    log::info!("Resuming the intake of {} tasks.", kind);
+                                    paused.retain(|paused_kind| *paused_kind != kind);
+                                }
+                                Ok(json!({ "paused": Self::kind_names(&paused) }))
+                            }
+                            ControlRequest::Tasks => Ok(Self::list_tasks(
+                                &queue,
+                                &in_progress,
+                                &retries,
+                                &paused,
+                                &model_config.borrow(),
+                            )),
+                            ControlRequest::Cancel(task_id) => {
+                                match Self::cancel_task(task_id, &mut queue, &mut retries, &in_progress, &mut checkpoint) {
+                                    0 => Err(format!("Task {} is not in progress or queued.", task_id)),
+                                    cancelled => Ok(json!({ "cancelled": cancelled })),
+                                }
+                            }
+                            // these take a while, so they are run alongside the loop & reply when done
+                            ControlRequest::SetModels(models) => {
+                                let kinds = &kinds;
+                                control_jobs.push(
+                                    async move {
+                                        let _ = reply.send(Self::set_models(models, kinds, model_config).await);
+                                    }
+                                    .boxed_local(),
+                                );
+                                continue;
+                            }
+                            ControlRequest::Claim => {
+                                let identities = &identities;
+                                control_jobs.push(
+                                    async move {
+                                        let _ = reply.send(Self::claim_all(identities).await);
+                                    }
+                                    .boxed_local(),
+                                );
+                                continue;
+                            }
+                        };
+                        // the client may have gone away, nothing to do then
+                        let _ = reply.send(response);
                     }
                     next = event_stream.next() => {
-                        match next {
-                            Some(Ok((event, log))) => {
-                                self.handle_event_log(event, log, &kinds, &model_config)
-                                    .await
+                        let Some(batch) = next else {
+                            log::warn!("Stream ended, waiting a bit before restarting.");
+                            break;
+                        };
+                        if let Some(health) = &options.health {
+                            health.record_poll();
+                        }
+
+                        for next in batch.events {
+                            match next {
+                                Ok((event, log)) if log.removed => {
+                                    Self::handle_removed_log(
+                                        &event,
+                                        &log,
+                                        &mut queue,
+                                        &mut retries,
+                                        &in_progress,
+                                        &mut checkpoint,
+                                    );
+                                }
+                                Ok((event, log)) => {
+                                    Self::cancel_outdated_tasks(&event, &log, &in_progress);
+                                    let task_id = event.taskId;
+                                    let block_number = log.block_number;
+                                    last_block = last_block.max(block_number);
+                                    if !queue.push(event, log) {
+                                        log::debug!("Skipping duplicate event for task {}.", task_id);
+                                        continue;
+                                    }
+                                    if let Some(block_number) = block_number {
+                                        checkpoint.start(block_number);
+                                    }
+                                    log::debug!(
+                                        "Tasks: {} in progress, {} in queue, {} to retry.",
+                                        workers.len(),
+                                        queue.len(),
+                                        retries.len()
+                                    );
+                                }
+                                Err(e) => log::error!("Could not handle event: {}", e),
                             }
-                            Some(Err(e)) => log::error!("Could not handle event: {}", e),
-                            None => {
-                                log::warn!("Stream ended, waiting a bit before restarting.");
-                                tokio::time::sleep(Duration::from_secs(5)).await;
-                                break
-                            },
+                        }
+
+                        // blocks without events are processed too, once the events before them are
+                        if let Some(synced_block) = batch.synced_block {
+                            last_block = last_block.max(Some(synced_block));
+                            Self::sync_checkpoint(&mut checkpoint, synced_block);
                         }
                     }
                 }
             }
+
+            // wait a bit before restarting the stream, while still processing the existing tasks
+            let wait = Duration::from_secs(5);
+            if !Self::wait_with_workers(
+                &mut workers,
+                &mut in_progress,
+                &mut retries,
+                &mut checkpoint,
+                wait,
+                &cancellation,
+            )
+            .await
+            {
+                break 'events;
+            }
         }
+
+        // stop accepting new events, and give the tasks in progress some time to finish
+        Self::drain_workers(
+            &mut workers,
+            &mut in_progress,
+            &mut retries,
+            &mut checkpoint,
+            options.grace_period,
+        )
+        .await;
+
+        Ok(())
     }
 
-    async fn handle_event_log(
+    /// Creates the workflows config for the given models, and checks their services.
+    pub(in crate::cli) async fn prepare_models(models: Vec<Model>) -> Result<DriaWorkflowsConfig> {
+        let mut model_config = DriaWorkflowsConfig::new(models);
+        if model_config.models.is_empty() {
+            return Err(eyre!("No models provided."));
+        }
+        let ollama_config = model_config.ollama.clone();
+        model_config = model_config.with_ollama_config(
+            ollama_config
+                .with_min_tps(5.0)
+                .with_timeout(Duration::from_secs(150)),
+        );
+        model_config.check_services().await?;
+
+        Ok(model_config)
+    }
+
+    /// Ensures that the models are suitable for the given kinds, i.e. validators must serve the GPT4o model.
+    fn check_validator_models(
+        kinds: &[OracleKind],
+        model_config: &DriaWorkflowsConfig,
+    ) -> Result<()> {
+        if kinds.contains(&OracleKind::Validator)
+            && !model_config
+                .models
+                .contains(&(ModelProvider::OpenAI, Model::GPT4o))
+        {
+            return Err(eyre!("Validator must have GPT4o model."));
+        }
+
+        Ok(())
+    }
+
+    /// Receives the next control request, or waits forever if the control API is disabled.
+    async fn next_control(
+        control: &mut Option<mpsc::Receiver<ControlMessage>>,
+    ) -> Option<ControlMessage> {
+        match control {
+            Some(control) => control.recv().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Prepares the given models & replaces the shared models with them, for the control API.
+    ///
+    /// The tasks in progress keep using the previous models.
+    async fn set_models(
+        models: Vec<Model>,
+        kinds: &[OracleKind],
+        model_config: &SharedModelConfig,
+    ) -> Result<Value, String> {
+        let config = Self::prepare_models(models)
+            .await
+            .and_then(|config| Self::check_validator_models(kinds, &config).map(|_| config))
+            .map_err(|e| format!("{:#}", e))?;
+
+        let names = Self::model_names(&config);
+        log::info!("Serving models: {}", names.join(", "));
+        *model_config.borrow_mut() = Rc::new(config);
+        Ok(json!({ "models": names }))
+    }
+
+    /// Claims the rewards of all identities, for the control API.
+    async fn claim_all(identities: &Identities) -> Result<Value, String> {
+        let mut claimed = Vec::new();
+        for identity in identities.iter() {
+            if let Err(e) = identity.node.claim_rewards().await {
+                return Err(format!("{:#} (claimed for: {})", e, claimed.join(", ")));
+            }
+            claimed.push(identity.node.address().to_string());
+        }
+
+        Ok(json!({ "claimed": claimed }))
+    }
+
+    fn kind_names(kinds: &[OracleKind]) -> Vec<String> {
+        kinds
+            .iter()
+            .map(|kind| kind.to_string().to_lowercase())
+            .collect()
+    }
+
+    fn model_names(model_config: &DriaWorkflowsConfig) -> Vec<String> {
+        model_config
+            .models
+            .iter()
+            .map(|(_, model)| model.to_string())
+            .collect()
+    }
+
+    /// Lists the tasks in progress, queued & waiting for a retry, along with the paused kinds & served models.
+    fn list_tasks(
+        queue: &TaskQueue,
+        in_progress: &InProgress,
+        retries: &RetryQueue,
+        paused: &[OracleKind],
+        model_config: &DriaWorkflowsConfig,
+    ) -> Value {
+        let task = |event: &StatusUpdate| {
+            json!({
+                "task_id": event.taskId.to_string(),
+                "status": TaskStatus::try_from(event.statusAfter).unwrap_or_default().to_string(),
+            })
+        };
+        let now = Instant::now();
+
+        json!({
+            "paused": Self::kind_names(paused),
+            "models": Self::model_names(model_config),
+            "in_progress": in_progress.values().map(|(event, _, _)| task(event)).collect::<Vec<_>>(),
+            "queued": queue.iter().map(|(event, _)| task(event)).collect::<Vec<_>>(),
+            "retrying": retries
+                .iter()
+                .map(|(due, event, _)| {
+                    let mut task = task(event);
+                    task["retry_in_secs"] = json!(due.saturating_duration_since(now).as_secs());
+                    task
+                })
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Cancels the given task, by removing it from the queues and cancelling its work in progress.
+    ///
+    /// Returns the number of events of the task that are cancelled.
+    fn cancel_task(
+        task_id: U256,
+        queue: &mut TaskQueue,
+        retries: &mut RetryQueue,
+        in_progress: &InProgress,
+        checkpoint: &mut CheckpointStore,
+    ) -> usize {
+        let keys = queue
+            .iter()
+            .map(|(event, log)| (event, log))
+            .chain(retries.iter().map(|(_, event, log)| (event, log)))
+            .filter(|(event, _)| event.taskId == task_id)
+            .map(|(_, log)| log_key(log))
+            .collect::<Vec<_>>();
+
+        let mut cancelled = 0;
+        for key in keys {
+            if let Some((event, log)) = queue.remove(&key).or_else(|| retries.remove(&key)) {
+                Self::finish_checkpoint(checkpoint, &event, &log);
+                cancelled += 1;
+            }
+        }
+
+        // tasks in progress are marked as processed once their workers return
+        for (event, task_cancellation, _) in in_progress.values() {
+            if event.taskId == task_id && !task_cancellation.is_cancelled() {
+                task_cancellation.cancel();
+                cancelled += 1;
+            }
+        }
+
+        if cancelled > 0 {
+            log::warn!("Task {} cancelled by the control API.", task_id);
+        }
+        cancelled
+    }
+
+    /// Runs the readiness checks periodically, and records their results to the health state.
+    ///
+    /// This future never returns, so it is meant to be polled alongside the event loop.
+    async fn check_readiness(
         &self,
+        identities: &Identities,
+        model_config: &SharedModelConfig,
+        health: &HealthState,
+    ) {
+        let mut interval = tokio::time::interval(READINESS_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let rpc = self.provider.get_block_number().await;
+            health.set(
+                Check::Rpc,
+                rpc.map(|_| ()).wrap_err("RPC is not responding"),
+            );
+
+            let contracts = match self.check_contract_sizes().await {
+                Ok(()) => self.check_contract_tokens().await,
+                Err(e) => Err(e),
+            };
+            health.set(Check::Contracts, contracts);
+
+            health.set(
+                Check::Registration,
+                Self::check_registrations(identities).await,
+            );
+
+            // checking the services may drop the unavailable models, so a copy is checked
+            let mut services_config = DriaWorkflowsConfig::clone(&model_config.borrow());
+            let services = services_config.check_services().await;
+            health.set(
+                Check::Services,
+                services.wrap_err("model services are not available"),
+            );
+        }
+    }
+
+    /// Ensures that the identities are still registered as their kinds,
+    /// and that they are still whitelisted if they are validators.
+    ///
+    /// Registrations are not required in dry-run mode.
+    async fn check_registrations(identities: &Identities) -> Result<()> {
+        for Identity { node, kinds } in identities.iter() {
+            if node.config.dry_run {
+                return Ok(());
+            }
+
+            for kind in kinds {
+                if !node.is_registered(*kind).await? {
+                    return Err(eyre!("{} is not registered as {}.", node.address(), kind));
+                }
+            }
+
+            if kinds.contains(&OracleKind::Validator)
+                && !node.is_whitelisted(node.address()).await?
+            {
+                return Err(eyre!(
+                    "{} is not whitelisted in the registry.",
+                    node.address()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks up the head block & the hashes of the blocks with the given numbers that have enough
+    /// confirmations, for [`DriaOracle::confirm_tasks`].
+    ///
+    /// The lookups are made alongside the event loop, so that a slow RPC does not block it.
+    async fn lookup_confirmations(
+        &self,
+        block_numbers: BTreeSet<u64>,
+        confirmations: u64,
+    ) -> Lookup {
+        let lookup = async {
+            let head = self.provider.get_block_number().await?;
+
+            let mut block_hashes = HashMap::new();
+            for block_number in block_numbers {
+                if block_number.saturating_add(confirmations) > head {
+                    continue;
+                }
+                block_hashes.insert(block_number, self.get_block_hash(block_number).await);
+            }
+
+            Ok((head, block_hashes))
+        };
+
+        match tokio::time::timeout(LOOKUP_TIMEOUT, lookup).await {
+            Ok(Ok((head, block_hashes))) => Lookup::Confirmations(Some(head), block_hashes),
+            Ok(Err(e)) => {
+                log::error!("Could not get block number: {:?}", e);
+                Lookup::Confirmations(None, HashMap::new())
+            }
+            Err(_) => {
+                log::error!("Could not check confirmations in {:?}.", LOOKUP_TIMEOUT);
+                Lookup::Confirmations(None, HashMap::new())
+            }
+        }
+    }
+
+    /// Moves the queued tasks with enough confirmations w.r.t the looked up head to the ready queue.
+    ///
+    /// Tasks with events that are not in the canonical chain anymore are dropped, and the ones
+    /// whose blocks are not looked up, e.g. they are queued in the meantime, wait for the next check.
+    fn confirm_tasks(
+        head: Option<u64>,
+        block_hashes: HashMap<u64, Result<Option<B256>>>,
+        queue: &mut TaskQueue,
+        checkpoint: &mut CheckpointStore,
+    ) {
+        let Some(head) = head else {
+            return;
+        };
+
+        for (event, log) in queue.take_confirmed(head) {
+            let Some(block_number) = log.block_number else {
+                queue.push_ready(event, log);
+                continue;
+            };
+
+            let block_hash = match block_hashes.get(&block_number) {
+                Some(Ok(block_hash)) => *block_hash,
+                Some(Err(e)) => {
+                    // keep it in the queue to try again later
+                    log::error!("Could not get block {}: {:?}", block_number, e);
+                    queue.push_unconfirmed(event, log);
+                    continue;
+                }
+                None => {
+                    queue.push_unconfirmed(event, log);
+                    continue;
+                }
+            };
+
+            if block_hash.is_some() && block_hash == log.block_hash {
+                queue.push_ready(event, log);
+            } else {
+                log::warn!(
+                    "Task {} event at block {} is not in the canonical chain, dropping it.",
+                    event.taskId,
+                    block_number
+                );
+                Self::finish_checkpoint(checkpoint, &event, &log);
+            }
+        }
+    }
+
+    /// Looks up the on-chain statuses of the tasks that are due for a retry, for [`DriaOracle::retry_tasks`].
+    ///
+    /// The lookups are made alongside the event loop, so that a slow RPC does not block it.
+    async fn lookup_retries(&self, due: Vec<(LogKey, U256)>) -> Lookup {
+        let lookup = async {
+            let mut statuses = Vec::with_capacity(due.len());
+            for (key, task_id) in due {
+                let status = self
+                    .get_task_request(task_id)
+                    .await
+                    .map(|request| request.status);
+                statuses.push((key, status));
+            }
+            statuses
+        };
+
+        match tokio::time::timeout(LOOKUP_TIMEOUT, lookup).await {
+            Ok(statuses) => Lookup::Retries(statuses),
+            Err(_) => {
+                // the tasks are still due, so they are looked up again
+                log::error!(
+                    "Could not check the tasks to retry in {:?}.",
+                    LOOKUP_TIMEOUT
+                );
+                Lookup::Retries(Vec::new())
+            }
+        }
+    }
+
+    /// Moves the failed tasks that are due for a retry back to the queue, w.r.t their looked up statuses.
+    ///
+    /// Tasks that have moved past the status of their event are dropped as there is nothing to do for them,
+    /// and the ones that are removed in the meantime, e.g. cancelled, are skipped.
+    fn retry_tasks(
+        statuses: Vec<(LogKey, Result<u8>)>,
+        queue: &mut TaskQueue,
+        retries: &mut RetryQueue,
+        checkpoint: &mut CheckpointStore,
+    ) {
+        for (key, status) in statuses {
+            let Some((event, log)) = retries.take(&key) else {
+                continue;
+            };
+
+            match status {
+                Ok(status) if status == event.statusAfter => {
+                    log::info!("Retrying task {}.", event.taskId);
+                    queue.push_ready(event, log);
+                }
+                Ok(status) => {
+                    log::info!(
+                        "Task {} is {} now, dropping its retry.",
+                        event.taskId,
+                        TaskStatus::try_from(status).unwrap_or_default()
+                    );
+                    retries.clear(&key);
+                    Self::finish_checkpoint(checkpoint, &event, &log);
+                }
+                Err(e) => {
+                    let e = e.wrap_err("could not get task status");
+                    Self::retry_task(event, log, e, retries, checkpoint);
+                }
+            }
+        }
+    }
+
+    /// Handles the result of a task that a worker has finished.
+    ///
+    /// Failed tasks are scheduled for a retry if their error is retryable, or deferred
+    /// without using up their retries if the fees are above the ceiling; otherwise the
+    /// task is marked as processed.
+    fn finish_task(
         event: StatusUpdate,
         log: Log,
-        kinds: &[OracleKind],
-        model_config: &DriaWorkflowsConfig,
+        result: Result<()>,
+        in_progress: &mut InProgress,
+        retries: &mut RetryQueue,
+        checkpoint: &mut CheckpointStore,
+    ) {
+        let key = log_key(&log);
+        in_progress.remove(&key);
+        match result {
+            Ok(()) => {
+                retries.clear(&key);
+                Self::finish_checkpoint(checkpoint, &event, &log);
+            }
+            Err(e) if is_fee_ceiling_error(&e) => {
+                log::warn!(
+                    "Task {} deferred for {:?}: {}",
+                    event.taskId,
+                    FEE_DEFER_DELAY,
+                    e.root_cause()
+                );
+                retries.defer(event, log, FEE_DEFER_DELAY);
+            }
+            Err(e) if is_retryable_error(&e) => {
+                Self::retry_task(event, log, e, retries, checkpoint);
+            }
+            Err(e) => {
+                log::error!("Could not process task {}: {:?}", event.taskId, e);
+                retries.clear(&key);
+                Self::finish_checkpoint(checkpoint, &event, &log);
+            }
+        }
+    }
+
+    /// Schedules a failed task for a retry, or drops it if it has no retries left.
+    fn retry_task(
+        event: StatusUpdate,
+        log: Log,
+        error: eyre::Report,
+        retries: &mut RetryQueue,
+        checkpoint: &mut CheckpointStore,
     ) {
         let task_id = event.taskId;
+        let attempts = retries.attempts(&log_key(&log)) + 1;
+        match retries.schedule(event.clone(), log.clone()) {
+            Some(delay) => log::warn!(
+                "Task {} failed (attempt {}), retrying in {:?}: {:?}",
+                task_id,
+                attempts,
+                delay,
+                error
+            ),
+            None => {
+                log::error!(
+                    "Could not process task {} after {} attempts: {:?}",
+                    task_id,
+                    attempts,
+                    error
+                );
+                Self::finish_checkpoint(checkpoint, &event, &log);
+            }
+        }
+    }
+
+    /// Handles a removed log due to a reorg, by removing its task from the queues
+    /// or cancelling it if it is being processed.
+    fn handle_removed_log(
+        event: &StatusUpdate,
+        log: &Log,
+        queue: &mut TaskQueue,
+        retries: &mut RetryQueue,
+        in_progress: &InProgress,
+        checkpoint: &mut CheckpointStore,
+    ) {
+        // the event may be emitted again in another block, so we forget about it
+        queue.forget(event);
+
+        let key = log_key(log);
+        if let Some((event, log)) = queue.remove(&key).or_else(|| retries.remove(&key)) {
+            log::warn!(
+                "Task {} event removed due to a reorg, dropping it.",
+                event.taskId
+            );
+            Self::finish_checkpoint(checkpoint, &event, &log);
+        } else if let Some((_, task_cancellation, _)) = in_progress.get(&key) {
+            log::warn!(
+                "Task {} event removed due to a reorg, cancelling it.",
+                event.taskId
+            );
+            task_cancellation.cancel();
+        } else {
+            log::warn!("Task {} event removed due to a reorg.", event.taskId);
+        }
+    }
+
+    /// Cancels the tasks in progress with the same task id as the given event, as the task
+    /// has a new status and their work would be wasted, e.g. other generators have filled the task.
+    ///
+    /// Tasks whose responses are being submitted are not cancelled, as their work is done already;
+    /// and the new status may well be due to their own responses.
+    fn cancel_outdated_tasks(event: &StatusUpdate, log: &Log, in_progress: &InProgress) {
+        let key = log_key(log);
+        for (k, (task_event, task_cancellation, submitted)) in in_progress {
+            if *k == key
+                || task_event.taskId != event.taskId
+                || task_cancellation.is_cancelled()
+                || submitted.get()
+            {
+                continue;
+            }
+
+            log::warn!(
+                "Task {} has moved to {}, cancelling the work in progress.",
+                event.taskId,
+                TaskStatus::try_from(event.statusAfter).unwrap_or_default()
+            );
+            task_cancellation.cancel();
+        }
+    }
+
+    /// Keeps processing the tasks in progress for the given duration, without starting new ones.
+    ///
+    /// Returns `false` if cancellation is received in the meantime.
+    async fn wait_with_workers<F: Future<Output = (StatusUpdate, Log, Result<()>)>>(
+        workers: &mut FuturesUnordered<F>,
+        in_progress: &mut InProgress,
+        retries: &mut RetryQueue,
+        checkpoint: &mut CheckpointStore,
+        duration: Duration,
+        cancellation: &CancellationToken,
+    ) -> bool {
+        let wait = tokio::time::sleep(duration);
+        tokio::pin!(wait);
+        loop {
+            tokio::select! {
+                _ = cancellation.cancelled() => {
+                    log::debug!("Cancellation signal received. Stopping...");
+                    return false;
+                }
+                Some((event, log, result)) = workers.next(), if !workers.is_empty() => {
+                    Self::finish_task(event, log, result, in_progress, retries, checkpoint);
+                }
+                _ = &mut wait => return true,
+            }
+        }
+    }
+
+    /// Waits for the tasks in progress to finish within the given grace period, without starting new ones.
+    ///
+    /// Tasks that are still in progress after the grace period are abandoned, and as they are not
+    /// marked as processed within the checkpoint, they are picked up again when the node restarts.
+    async fn drain_workers<F: Future<Output = (StatusUpdate, Log, Result<()>)>>(
+        workers: &mut FuturesUnordered<F>,
+        in_progress: &mut InProgress,
+        retries: &mut RetryQueue,
+        checkpoint: &mut CheckpointStore,
+        grace_period: Duration,
+    ) {
+        if workers.is_empty() {
+            return;
+        }
+
+        log::info!(
+            "Waiting up to {:?} for {} task(s) in progress.",
+            grace_period,
+            workers.len()
+        );
+        let drain = async {
+            while let Some((event, log, result)) = workers.next().await {
+                Self::finish_task(event, log, result, in_progress, retries, checkpoint);
+            }
+        };
+        if tokio::time::timeout(grace_period, drain).await.is_err() {
+            log::warn!(
+                "Grace period is over, abandoning {} task(s) in progress.",
+                workers.len()
+            );
+        } else {
+            log::info!("All tasks in progress are finished.");
+        }
+    }
+
+    /// Handles a single task with the timeout & protocol filter of the given options, and logs its result.
+    /// The task is handled as the identity that is chosen for it, see [`Identities::choose`].
+    /// The task is stopped if the given cancellation token is cancelled.
+    ///
+    /// Returns the event & log back along with the error if the task has failed,
+    /// so that they can be checkpointed or retried.
+    async fn handle_event_log(
+        &self,
+        event: StatusUpdate,
+        log: Log,
+        identities: &Identities,
+        model_config: Rc<DriaWorkflowsConfig>,
+        options: &RunOptions,
+        cancellation: CancellationToken,
+        submitted: Rc<Cell<bool>>,
+    ) -> (StatusUpdate, Log, Result<()>) {
+        let task_id = event.taskId;
+        let timeout = options.task_timeout;
         log::debug!(
             "Handling task {} (tx: {})",
             task_id,
             log.transaction_hash.unwrap_or_default()
         );
 
-        // handle request
-        match handle_request(self, kinds, model_config, event).await {
-            Ok(Some(receipt)) => {
+        // handle request as the chosen identity
+        let request = async {
+            let Some(identity) = identities.choose(&event).await? else {
+                return Ok(None);
+            };
+
+            handle_request(
+                &identity.node,
+                &identity.kinds,
+                &model_config,
+                &options.protocols,
+                options.profitability.as_ref(),
+                options.journal.as_ref(),
+                Some(&submitted),
+                event.clone(),
+            )
+            .await
+        };
+        let result = tokio::select! {
+            _ = cancellation.cancelled() => {
+                log::warn!("Task {} cancelled.", task_id);
+                return (event, log, Ok(()));
+            }
+            result = tokio::time::timeout(timeout, request) => result,
+        };
+        let result = match result {
+            Ok(Ok(Some(receipt))) => {
                 log::info!(
                     "Task {} processed successfully. (tx: {})",
                     task_id,
                     receipt.transaction_hash
-                )
+                );
+                Ok(())
             }
-            Ok(None) => {
-                log::debug!("Task {} ignored.", task_id)
+            Ok(Ok(None)) => {
+                log::debug!("Task {} ignored.", task_id);
+                Ok(())
             }
-            Err(e) => log::error!("Could not process task: {:?}", e),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(eyre!("Task {} timed out after {:?}.", task_id, timeout)),
+        };
+
+        (event, log, result)
+    }
+
+    /// Marks a handled event as processed within the checkpoint.
+    fn finish_checkpoint(checkpoint: &mut CheckpointStore, event: &StatusUpdate, log: &Log) {
+        let Some(block_number) = log.block_number else {
+            return;
+        };
+        if let Err(e) = checkpoint.finish(block_number, event.taskId, event.statusAfter) {
+            log::error!("Could not save checkpoint: {:?}", e);
+        }
+    }
+
+    /// Marks the events until and including the given block as seen within the checkpoint.
+    fn sync_checkpoint(checkpoint: &mut CheckpointStore, block_number: u64) {
+        if let Err(e) = checkpoint.sync(block_number) {
+            log::error!("Could not save checkpoint: {:?}", e);
         }
     }
 
+    /// Fetches the tasks from `from_block` until `to_block`, and adds them to the queue.
+    ///
+    /// Tasks that are already processed w.r.t the checkpoint or already queued are skipped,
+    /// and the rest wait for their confirmations within the queue just like the new ones.
     async fn handle_previous_tasks(
         &self,
         from_block: BlockNumberOrTag,
-        model_config: &DriaWorkflowsConfig,
-        kinds: &[OracleKind],
+        to_block: u64,
+        queue: &mut TaskQueue,
+        checkpoint: &mut CheckpointStore,
     ) -> Result<()> {
         log::info!(
-            "Checking previous tasks from block {} until now.",
-            from_block
+            "Checking previous tasks from block {} until {}.",
+            from_block,
+            to_block
         );
         let prev_tasks = self
-            .get_tasks_in_range(from_block, BlockNumberOrTag::Latest)
+            .get_tasks_in_range_chunked(from_block, to_block)
             .await?;
+        let mut prev_tasks = pin!(prev_tasks);
 
-        for (event, log) in prev_tasks {
-            let task_id = event.taskId;
-            log::info!(
-                "Previous task: {} ({} -> {})",
-                task_id,
-                TaskStatus::try_from(event.statusBefore).unwrap_or_default(),
-                TaskStatus::try_from(event.statusAfter).unwrap_or_default()
-            );
-            log::debug!(
-                "Handling task {} (tx: {})",
-                task_id,
-                log.transaction_hash.unwrap_or_default()
-            );
-            match handle_request(self, kinds, model_config, event).await {
-                Ok(Some(receipt)) => {
-                    log::info!(
-                        "Task {} processed successfully. (tx: {})",
-                        task_id,
-                        receipt.transaction_hash
-                    )
+        while let Some(tasks) = prev_tasks.try_next().await? {
+            for (event, log) in tasks {
+                let block_number = log.block_number;
+                if let Some(block_number) = block_number {
+                    if checkpoint.is_processed(block_number, event.taskId, event.statusAfter) {
+                        log::debug!("Skipping already processed task {}.", event.taskId);
+                        continue;
+                    }
+                }
+
+                let (task_id, status_before, status_after) =
+                    (event.taskId, event.statusBefore, event.statusAfter);
+                if !queue.push(event, log) {
+                    log::debug!("Skipping already queued task {}.", task_id);
+                    continue;
                 }
-                Ok(None) => {
-                    log::info!("Task {} ignored.", task_id)
+                if let Some(block_number) = block_number {
+                    checkpoint.start(block_number);
                 }
-                Err(e) => log::error!("Could not process task: {:?}", e),
+
+                log::info!(
+                    "Previous task: {} ({} -> {})",
+                    task_id,
+                    TaskStatus::try_from(status_before).unwrap_or_default(),
+                    TaskStatus::try_from(status_after).unwrap_or_default()
+                );
             }
         }
 
         Ok(())
     }
+
     pub(in crate::cli) async fn view_task_events(
         &self,
         from_block: impl Into<BlockNumberOrTag> + Clone,
@@ -231,15 +1227,20 @@ impl DriaOracle {
                 .unwrap_or(to_block.to_string())
         );
 
-        let task_events = self.get_tasks_in_range(from_block, to_block).await?;
+        let task_events = self
+            .get_tasks_in_range_chunked(from_block, to_block)
+            .await?;
+        let mut task_events = pin!(task_events);
 
-        for (event, _) in task_events {
-            log::info!(
-                "Task: {} ({} -> {})",
-                event.taskId,
-                TaskStatus::try_from(event.statusBefore).unwrap_or_default(),
-                TaskStatus::try_from(event.statusAfter).unwrap_or_default()
-            );
+        while let Some(events) = task_events.try_next().await? {
+            for (event, _) in events {
+                log::info!(
+                    "Task: {} ({} -> {})",
+                    event.taskId,
+                    TaskStatus::try_from(event.statusBefore).unwrap_or_default(),
+                    TaskStatus::try_from(event.statusAfter).unwrap_or_default()
+                );
+            }
         }
 
         Ok(())
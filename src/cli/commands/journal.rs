@@ -0,0 +1,46 @@
+use crate::{contracts::ADDRESSES, DriaOracle, Journal};
+use alloy::primitives::{Address, U256};
+use alloy_chains::Chain;
+use eyre::{eyre, Context, Result};
+use std::path::Path;
+
+impl DriaOracle {
+    /// Opens the local journal of this chain & coordinator within the data directory.
+    pub(in crate::cli) fn open_journal(&self, data_dir: &Path) -> Result<Journal> {
+        Journal::open(data_dir, self.chain_id, self.addresses.coordinator)
+            .wrap_err("could not open journal")
+    }
+}
+
+/// Displays the journal entries of the given task, or the latest entries if no task is given.
+///
+/// The journal is local, so this does not connect to the chain; the coordinator defaults
+/// to the known one of the given chain.
+pub(in crate::cli) fn display_journal(
+    data_dir: &Path,
+    chain_id: u64,
+    coordinator: Option<Address>,
+    task_id: Option<U256>,
+    limit: usize,
+) -> Result<()> {
+    let coordinator = match coordinator {
+        Some(coordinator) => coordinator,
+        None => ADDRESSES
+            .get(&Chain::from_id(chain_id))
+            .map(|addresses| addresses.coordinator)
+            .ok_or_else(|| eyre!("No contract addresses are known for chain {}.", chain_id))?,
+    };
+
+    let journal =
+        Journal::open(data_dir, chain_id, coordinator).wrap_err("could not open journal")?;
+    let entries = journal.entries(task_id, limit)?;
+    if entries.is_empty() {
+        log::warn!("There are no entries in the journal.");
+    }
+
+    for entry in entries.iter().rev() {
+        log::info!("{}", entry);
+    }
+
+    Ok(())
+}
@@ -1,12 +1,21 @@
 mod coordinator;
+mod journal;
 mod registry;
 mod token;
 
+pub use coordinator::{RunOptions, SharedModelConfig};
+pub(in crate::cli) use journal::display_journal;
+
 use super::parsers::*;
-use crate::contracts::OracleKind;
-use alloy::{eips::BlockNumberOrTag, primitives::U256};
+use crate::{contracts::OracleKind, ProtocolPattern};
+use alloy::{
+    eips::BlockNumberOrTag,
+    primitives::{Address, U256},
+};
+use alloy_chains::NamedChain;
 use clap::Subcommand;
 use dkn_workflows::Model;
+use std::{net::SocketAddr, path::PathBuf};
 
 // https://docs.rs/clap/latest/clap/_derive/index.html#arg-attributes
 #[derive(Subcommand)]
@@ -33,7 +42,7 @@ pub enum Commands {
     Start {
         #[arg(
             long,
-            help = "Starting block number to listen for, defaults to 'latest'.",
+            help = "Starting block number to listen for, defaults to the checkpoint or 'latest'.",
             value_parser = parse_block_number_or_tag
         )]
         from: Option<BlockNumberOrTag>,
@@ -41,6 +50,142 @@ pub enum Commands {
         kinds: Vec<OracleKind>,
         #[arg(short, long = "model", help = "The models to serve.", required = true, value_parser = parse_model)]
         models: Vec<Model>,
+        #[arg(
+            long,
+            help = "Maximum number of tasks to be processed at the same time.",
+            default_value_t = 4
+        )]
+        max_tasks: usize,
+        #[arg(
+            long,
+            help = "Timeout for a single task in seconds.",
+            default_value_t = 300
+        )]
+        task_timeout: u64,
+        #[arg(
+            long,
+            env = "DATA_DIR",
+            help = "Directory to store local data such as the checkpoint.",
+            default_value = "./data"
+        )]
+        data_dir: PathBuf,
+        #[arg(
+            long,
+            help = "Ignore & delete the existing checkpoint.",
+            default_value_t = false
+        )]
+        reset_checkpoint: bool,
+        #[arg(
+            long,
+            help = "Number of blocks to wait on top of a task's block before handling it.",
+            default_value_t = 0
+        )]
+        confirmations: u64,
+        #[arg(
+            long,
+            help = "Maximum number of retries for a task that failed with a retryable error.",
+            default_value_t = 3
+        )]
+        max_retries: u32,
+        #[arg(
+            long,
+            help = "Delay before the first retry of a failed task in seconds, doubled for each retry.",
+            default_value_t = 5
+        )]
+        retry_delay: u64,
+        #[arg(
+            long,
+            help = "Time to wait for the tasks in progress when shutting down, in seconds.",
+            default_value_t = 60
+        )]
+        grace_period: u64,
+        #[arg(
+            long = "include-protocol",
+            help = "Only serve tasks with protocols matching these patterns, e.g. 'swan-*' or 'swan-buyer-purchase/>=0.1.0, <0.2.0'.",
+            value_parser = parse_protocol_pattern
+        )]
+        include_protocols: Vec<ProtocolPattern>,
+        #[arg(
+            long = "exclude-protocol",
+            help = "Do not serve tasks with protocols matching these patterns.",
+            value_parser = parse_protocol_pattern
+        )]
+        exclude_protocols: Vec<ProtocolPattern>,
+        #[arg(
+            long,
+            help = "Skip the tasks with expected rewards below their estimated cost plus this margin, e.g. 0.1 for 10%."
+        )]
+        min_profit_margin: Option<f64>,
+        #[arg(
+            long = "model-price",
+            help = "Price per 1M tokens of a model in ether for the profitability check, e.g. 'gpt-4o-mini=0.0002'.",
+            value_parser = parse_model_price
+        )]
+        model_prices: Vec<(Model, f64)>,
+        #[arg(
+            long,
+            env = "METRICS_ADDR",
+            help = "Serve Prometheus metrics at /metrics and health checks at /healthz & /readyz on the given address, defaults to 0.0.0.0:9090 if no address is given.",
+            num_args = 0..=1,
+            default_missing_value = "0.0.0.0:9090"
+        )]
+        metrics: Option<SocketAddr>,
+        #[arg(
+            long,
+            env = "HEALTH_ADDR",
+            help = "Serve health checks at /healthz & /readyz on the given address, without the metrics, defaults to 0.0.0.0:9092 if no address is given.",
+            num_args = 0..=1,
+            default_missing_value = "0.0.0.0:9092"
+        )]
+        health: Option<SocketAddr>,
+        #[arg(
+            long,
+            help = "Maximum time since the last poll of the event stream for the node to be ready, in seconds.",
+            default_value_t = 120
+        )]
+        max_poll_age: u64,
+        #[arg(
+            long,
+            help = "Do not record the handled tasks to the local journal.",
+            default_value_t = false
+        )]
+        no_journal: bool,
+        #[arg(
+            long,
+            help = "Handle the tasks without uploading or responding, and log the responses instead. Registrations are not required.",
+            default_value_t = false
+        )]
+        dry_run: bool,
+        #[arg(
+            long,
+            env = "CONTROL_ADDR",
+            help = "Serve the control API for `ctl` commands on the given loopback address, defaults to 127.0.0.1:9091 if no address is given.",
+            num_args = 0..=1,
+            default_missing_value = "127.0.0.1:9091",
+            value_parser = parse_loopback_addr
+        )]
+        control: Option<SocketAddr>,
+        #[arg(
+            long = "chain",
+            env = "CHAINS",
+            help = "Another chain to serve alongside the one of the RPC URL, as '<rpc-url>[,<fallback-rpc-url>...][#<token>,<registry>,<coordinator>]' where the addresses default to the known ones of the chain.",
+            value_delimiter = ';',
+            value_parser = parse_chain
+        )]
+        chains: Vec<ChainSpec>,
+    },
+    /// Control a running oracle node, see `--control` of `start`.
+    Ctl {
+        #[arg(
+            long,
+            env = "CONTROL_ADDR",
+            help = "Address of the control API of the node.",
+            default_value = "127.0.0.1:9091",
+            value_parser = parse_loopback_addr
+        )]
+        addr: SocketAddr,
+        #[command(subcommand)]
+        command: CtlCommands,
     },
     /// View status of a given task.
     View { task_id: U256 },
@@ -51,6 +196,35 @@ pub enum Commands {
         #[arg(long, help = "Ending block number, defaults to 'latest'.", value_parser = parse_block_number_or_tag)]
         to: Option<BlockNumberOrTag>,
     },
+    /// View the local journal of the handled tasks.
+    Journal {
+        #[arg(help = "The task to view the entries of, defaults to the latest entries.")]
+        task_id: Option<U256>,
+        #[arg(
+            long,
+            help = "Maximum number of entries to view.",
+            default_value_t = 10
+        )]
+        limit: usize,
+        #[arg(
+            long,
+            env = "DATA_DIR",
+            help = "Directory where the journal is stored.",
+            default_value = "./data"
+        )]
+        data_dir: PathBuf,
+        #[arg(
+            long,
+            help = "Chain id of the journal, as the journal is read without an RPC.",
+            default_value_t = NamedChain::BaseSepolia as u64
+        )]
+        chain_id: u64,
+        #[arg(
+            long,
+            help = "Coordinator address of the journal, defaults to the known one of the chain."
+        )]
+        coordinator: Option<Address>,
+    },
     /// Request a task.
     Request {
         #[arg(help = "The input to request a task with.", required = true)]
@@ -73,3 +247,29 @@ pub enum Commands {
         num_vals: u64,
     },
 }
+
+/// Commands for a running oracle node.
+#[derive(Subcommand)]
+pub enum CtlCommands {
+    /// Pause the intake of tasks of the given kinds, the tasks in progress are kept.
+    Pause {
+        #[arg(help = "The oracle kinds to pause.", required = true)]
+        kinds: Vec<OracleKind>,
+    },
+    /// Resume the intake of tasks of the given kinds.
+    Resume {
+        #[arg(help = "The oracle kinds to resume.", required = true)]
+        kinds: Vec<OracleKind>,
+    },
+    /// List the tasks in progress, queued & waiting for a retry.
+    Tasks,
+    /// Cancel a task that is in progress, queued or waiting for a retry.
+    Cancel { task_id: U256 },
+    /// Replace the served models.
+    Models {
+        #[arg(help = "The models to serve.", required = true, value_parser = parse_model)]
+        models: Vec<Model>,
+    },
+    /// Claim rewards from the coordinator.
+    Claim,
+}
@@ -1,5 +1,6 @@
-use crate::DriaOracle;
+use crate::{metrics, DriaOracle};
 use eyre::Result;
+use std::time::Duration;
 
 impl DriaOracle {
     /// Display token balances
@@ -30,6 +31,39 @@ impl DriaOracle {
         Ok(())
     }
 
+    /// Updates the balance & claimable reward metrics periodically, forever.
+    pub(in crate::cli) async fn report_metrics(&self, interval: Duration) {
+        loop {
+            if let Err(e) = self.update_metrics().await {
+                log::warn!("Could not update metrics: {:?}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Updates the balance & claimable reward metrics of the node's wallet, where rewards
+    /// are read from the same allowance as [`DriaOracle::display_rewards`].
+    async fn update_metrics(&self) -> Result<()> {
+        let eth_balance = self.get_native_balance(self.address()).await?;
+        let token_balance = self.get_token_balance(self.address()).await?;
+        let chain = self.chain_id.to_string();
+        let address = self.address().to_string();
+        for balance in [eth_balance, token_balance].iter() {
+            metrics::BALANCE
+                .with_label_values(&[&chain, &address, &balance.symbol])
+                .set(metrics::to_ether(balance.amount));
+        }
+
+        let allowance = self
+            .allowance(self.addresses.coordinator, self.address())
+            .await?;
+        metrics::CLAIMABLE_REWARDS
+            .with_label_values(&[&chain, &address, &allowance.symbol])
+            .set(metrics::to_ether(allowance.amount));
+
+        Ok(())
+    }
+
     /// Claim rewards
     pub(in crate::cli) async fn claim_rewards(&self) -> Result<()> {
         // get allowance
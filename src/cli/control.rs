@@ -0,0 +1,265 @@
+use super::{commands::CtlCommands, parsers::parse_model};
+use crate::{contracts::OracleKind, OracleCoordinator::StatusUpdate, TaskStatus};
+use alloy::primitives::U256;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post, put},
+    Json, Router,
+};
+use clap::ValueEnum;
+use dkn_workflows::Model;
+use eyre::{eyre, Context, Result};
+use reqwest::Method;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, oneshot},
+};
+use tokio_util::sync::CancellationToken;
+
+/// A request to the running node, made through the control API.
+#[derive(Debug)]
+pub enum ControlRequest {
+    /// Stop starting new tasks of the given kind, the queued ones are kept.
+    Pause(OracleKind),
+    /// Start the tasks of the given kind again.
+    Resume(OracleKind),
+    /// List the tasks in progress, queued & waiting for a retry.
+    Tasks,
+    /// Cancel a task, whether it is in progress, queued or waiting for a retry.
+    Cancel(U256),
+    /// Replace the served models, after checking their services.
+    SetModels(Vec<Model>),
+    /// Claim the rewards from the coordinator.
+    Claim,
+}
+
+/// Response of the node to a control request, with an error message if it has failed.
+pub type ControlResponse = std::result::Result<Value, String>;
+
+/// A control request along with the channel to reply to.
+pub type ControlMessage = (ControlRequest, oneshot::Sender<ControlResponse>);
+
+/// Returns the oracle kind that would handle a task event, if any.
+pub fn task_kind(event: &StatusUpdate) -> Option<OracleKind> {
+    match TaskStatus::try_from(event.statusAfter) {
+        Ok(TaskStatus::PendingGeneration) => Some(OracleKind::Generator),
+        Ok(TaskStatus::PendingValidation) => Some(OracleKind::Validator),
+        _ => None,
+    }
+}
+
+/// Serves the control API of the node at the given address, until cancelled.
+///
+/// Requests are forwarded to the event loop through the given channel, and each of them
+/// is answered with a JSON body, or with `{ "error": ... }` along with a non-200 status.
+///
+/// - `POST /pause/:kind` & `POST /resume/:kind` pause & resume the intake of a kind.
+/// - `GET /tasks` lists the tasks in progress, queued & waiting for a retry.
+/// - `POST /tasks/:task_id/cancel` cancels a task.
+/// - `PUT /models` replaces the served models with a JSON array of model names.
+/// - `POST /claim` claims the rewards.
+///
+/// The API is not authenticated, so the address is expected to be a loopback address.
+pub async fn serve(
+    addr: SocketAddr,
+    sender: mpsc::Sender<ControlMessage>,
+    cancellation: CancellationToken,
+) -> Result<()> {
+    let app = Router::new()
+        .route("/pause/:kind", post(pause))
+        .route("/resume/:kind", post(resume))
+        .route("/tasks", get(tasks))
+        .route("/tasks/:task_id/cancel", post(cancel))
+        .route("/models", put(set_models))
+        .route("/claim", post(claim))
+        .with_state(sender);
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .wrap_err_with(|| format!("could not bind to {}", addr))?;
+    log::info!("Serving control API at http://{}", addr);
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { cancellation.cancelled().await })
+        .await
+        .wrap_err("could not serve control API")
+}
+
+type Reply = (StatusCode, Json<Value>);
+
+fn bad_request(message: String) -> Reply {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": message })))
+}
+
+/// Forwards a request to the event loop and waits for its response.
+async fn forward(sender: &mpsc::Sender<ControlMessage>, request: ControlRequest) -> Reply {
+    let (reply, response) = oneshot::channel();
+    if sender.send((request, reply)).await.is_err() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "node is not running" })),
+        );
+    }
+
+    match response.await {
+        Ok(Ok(value)) => (StatusCode::OK, Json(value)),
+        Ok(Err(e)) => bad_request(e),
+        Err(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": "node has stopped" })),
+        ),
+    }
+}
+
+fn parse_kind(kind: &str) -> std::result::Result<OracleKind, Reply> {
+    OracleKind::from_str(kind, true).map_err(|_| bad_request(format!("invalid kind: {}", kind)))
+}
+
+async fn pause(
+    State(sender): State<mpsc::Sender<ControlMessage>>,
+    Path(kind): Path<String>,
+) -> Reply {
+    match parse_kind(&kind) {
+        Ok(kind) => forward(&sender, ControlRequest::Pause(kind)).await,
+        Err(reply) => reply,
+    }
+}
+
+async fn resume(
+    State(sender): State<mpsc::Sender<ControlMessage>>,
+    Path(kind): Path<String>,
+) -> Reply {
+    match parse_kind(&kind) {
+        Ok(kind) => forward(&sender, ControlRequest::Resume(kind)).await,
+        Err(reply) => reply,
+    }
+}
+
+async fn tasks(State(sender): State<mpsc::Sender<ControlMessage>>) -> Reply {
+    forward(&sender, ControlRequest::Tasks).await
+}
+
+async fn cancel(
+    State(sender): State<mpsc::Sender<ControlMessage>>,
+    Path(task_id): Path<String>,
+) -> Reply {
+    match task_id.parse::<U256>() {
+        Ok(task_id) => forward(&sender, ControlRequest::Cancel(task_id)).await,
+        Err(_) => bad_request(format!("invalid task id: {}", task_id)),
+    }
+}
+
+async fn set_models(
+    State(sender): State<mpsc::Sender<ControlMessage>>,
+    Json(models): Json<Vec<String>>,
+) -> Reply {
+    let models = models
+        .iter()
+        .map(|model| parse_model(model))
+        .collect::<Result<Vec<_>>>();
+
+    match models {
+        Ok(models) if models.is_empty() => bad_request(String::from("no models provided")),
+        Ok(models) => forward(&sender, ControlRequest::SetModels(models)).await,
+        Err(e) => bad_request(e.to_string()),
+    }
+}
+
+async fn claim(State(sender): State<mpsc::Sender<ControlMessage>>) -> Reply {
+    forward(&sender, ControlRequest::Claim).await
+}
+
+/// Sends the given command to the control API of a running node at the given address,
+/// and logs its response.
+pub async fn run_ctl(addr: SocketAddr, command: CtlCommands) -> Result<()> {
+    let requests: Vec<(Method, String, Option<String>)> = match command {
+        CtlCommands::Pause { kinds } => kinds
+            .iter()
+            .map(|kind| (Method::POST, format!("/pause/{}", kind_path(kind)), None))
+            .collect(),
+        CtlCommands::Resume { kinds } => kinds
+            .iter()
+            .map(|kind| (Method::POST, format!("/resume/{}", kind_path(kind)), None))
+            .collect(),
+        CtlCommands::Tasks => vec![(Method::GET, String::from("/tasks"), None)],
+        CtlCommands::Cancel { task_id } => {
+            vec![(Method::POST, format!("/tasks/{}/cancel", task_id), None)]
+        }
+        CtlCommands::Models { models } => {
+            let models = models.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+            vec![(
+                Method::PUT,
+                String::from("/models"),
+                Some(serde_json::to_string(&models)?),
+            )]
+        }
+        CtlCommands::Claim => vec![(Method::POST, String::from("/claim"), None)],
+    };
+
+    let client = reqwest::Client::new();
+    for (method, path, body) in requests {
+        let mut request = client.request(method, format!("http://{}{}", addr, path));
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .wrap_err_with(|| format!("could not reach the control API at {}", addr))?;
+        let status = response.status();
+        let body: Value = serde_json::from_str(&response.text().await?)
+            .wrap_err("could not parse the response")?;
+
+        if !status.is_success() {
+            return Err(eyre!(
+                "Control request failed ({}): {}",
+                status,
+                body["error"].as_str().unwrap_or_default()
+            ));
+        }
+        log::info!("{}", serde_json::to_string_pretty(&body)?);
+    }
+
+    Ok(())
+}
+
+/// Name of the kind as it is used within the paths of the control API.
+fn kind_path(kind: &OracleKind) -> String {
+    kind.to_string().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_control_forward() {
+        let (sender, mut receiver) = mpsc::channel::<ControlMessage>(1);
+        tokio::spawn(async move {
+            while let Some((request, reply)) = receiver.recv().await {
+                let response = match request {
+                    ControlRequest::Pause(kind) => Ok(json!({ "paused": [kind_path(&kind)] })),
+                    _ => Err(String::from("unsupported")),
+                };
+                let _ = reply.send(response);
+            }
+        });
+
+        let (status, Json(body)) = pause(State(sender.clone()), Path("Generator".into())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["paused"][0], "generator");
+
+        let (status, _) = pause(State(sender.clone()), Path("miner".into())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, Json(body)) = claim(State(sender)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "unsupported");
+    }
+}
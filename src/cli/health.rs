@@ -0,0 +1,232 @@
+use eyre::Result;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A readiness check of the node, other than the liveness of the event stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Check {
+    /// The RPC responds to requests.
+    Rpc,
+    /// The contracts are deployed & use the expected token.
+    Contracts,
+    /// The node is registered as the served kinds, and whitelisted if it is a validator.
+    Registration,
+    /// The model services are available.
+    Services,
+}
+
+impl Check {
+    const ALL: [Check; 4] = [
+        Check::Rpc,
+        Check::Contracts,
+        Check::Registration,
+        Check::Services,
+    ];
+}
+
+impl std::fmt::Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Check::Rpc => write!(f, "rpc"),
+            Check::Contracts => write!(f, "contracts"),
+            Check::Registration => write!(f, "registration"),
+            Check::Services => write!(f, "services"),
+        }
+    }
+}
+
+/// Health state of the node, shared between the event loops and the HTTP server.
+///
+/// The node is ready once all checks have passed in their latest run, and the
+/// event stream has been polled within `max_poll_age`. Checks that have not run yet
+/// are considered failing, so that the node is not ready until it is fully started.
+///
+/// When serving multiple chains, each event loop records to its own view of the state,
+/// see [`HealthState::for_chain`], and the node is ready once all chains are ready.
+#[derive(Debug, Clone)]
+pub struct HealthState {
+    /// Maximum time since the last poll of the event stream for the node to be ready.
+    max_poll_age: Duration,
+    /// Chain that this view of the state records to, if it is for a specific chain.
+    chain_id: Option<u64>,
+    /// Latest results of the checks per chain, with the error message of the failing ones.
+    checks: Arc<Mutex<BTreeMap<(Option<u64>, Check), Result<(), String>>>>,
+    /// Time of the last poll of the event stream per chain, where the chains are known
+    /// to the state as soon as they are added to this map.
+    last_polls: Arc<Mutex<BTreeMap<Option<u64>, Option<Instant>>>>,
+}
+
+impl HealthState {
+    /// Creates a new health state, where the event stream must be polled within the given age.
+    pub fn new(max_poll_age: Duration) -> Self {
+        Self {
+            max_poll_age,
+            chain_id: None,
+            checks: Default::default(),
+            last_polls: Default::default(),
+        }
+    }
+
+    /// Returns a view of the state that records to the given chain, and adds the chain to the
+    /// state so that the node is not ready until the chain is ready as well.
+    pub fn for_chain(&self, chain_id: u64) -> Self {
+        self.last_polls
+            .lock()
+            .expect("health lock is poisoned")
+            .entry(Some(chain_id))
+            .or_default();
+
+        Self {
+            chain_id: Some(chain_id),
+            ..self.clone()
+        }
+    }
+
+    /// Records the result of a check, failing checks are logged.
+    pub fn set(&self, check: Check, result: Result<()>) {
+        let result = result.map_err(|e| format!("{:#}", e));
+        if let Err(e) = &result {
+            log::warn!("Readiness check {} failed: {}", self.name(check), e);
+        }
+
+        self.last_polls
+            .lock()
+            .expect("health lock is poisoned")
+            .entry(self.chain_id)
+            .or_default();
+        self.checks
+            .lock()
+            .expect("health lock is poisoned")
+            .insert((self.chain_id, check), result);
+    }
+
+    /// Records a poll of the event stream, e.g. when it yields events or a new block.
+    pub fn record_poll(&self) {
+        self.last_polls
+            .lock()
+            .expect("health lock is poisoned")
+            .insert(self.chain_id, Some(Instant::now()));
+    }
+
+    /// Returns whether the node is ready, along with the status of each check.
+    ///
+    /// Checks of a specific chain are named as `check@chain_id`, e.g. `rpc@84532`.
+    pub fn readiness(&self) -> (bool, Value) {
+        let mut ready = true;
+        let mut statuses = Map::new();
+
+        let checks = self.checks.lock().expect("health lock is poisoned");
+        let mut last_polls = self
+            .last_polls
+            .lock()
+            .expect("health lock is poisoned")
+            .clone();
+        if last_polls.is_empty() {
+            // nothing has been recorded yet, so the checks are pending
+            last_polls.insert(None, None);
+        }
+
+        for (chain_id, last_poll) in last_polls {
+            let view = Self {
+                chain_id,
+                ..self.clone()
+            };
+
+            for check in Check::ALL {
+                let status = match checks.get(&(chain_id, check)) {
+                    Some(Ok(())) => String::from("ok"),
+                    Some(Err(e)) => e.clone(),
+                    None => String::from("pending"),
+                };
+                ready &= status == "ok";
+                statuses.insert(view.name(check), Value::String(status));
+            }
+
+            let status = match last_poll.map(|poll| poll.elapsed()) {
+                Some(age) if age <= self.max_poll_age => String::from("ok"),
+                Some(age) => format!("last polled {}s ago", age.as_secs()),
+                None => String::from("pending"),
+            };
+            ready &= status == "ok";
+            statuses.insert(view.name("events"), Value::String(status));
+        }
+
+        (ready, json!({ "ready": ready, "checks": statuses }))
+    }
+
+    /// Returns the name of a check within this view of the state.
+    fn name(&self, check: impl std::fmt::Display) -> String {
+        match self.chain_id {
+            Some(chain_id) => format!("{}@{}", check, chain_id),
+            None => check.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eyre::eyre;
+
+    #[test]
+    fn test_readiness() {
+        let health = HealthState::new(Duration::from_secs(60));
+        let (ready, status) = health.readiness();
+        assert!(!ready);
+        assert_eq!(status["checks"]["rpc"], "pending");
+
+        for check in Check::ALL {
+            health.set(check, Ok(()));
+        }
+        assert!(!health.readiness().0);
+        health.record_poll();
+        assert!(health.readiness().0);
+
+        health.set(Check::Services, Err(eyre!("Ollama is not running")));
+        let (ready, status) = health.readiness();
+        assert!(!ready);
+        assert_eq!(status["checks"]["services"], "Ollama is not running");
+        assert_eq!(status["checks"]["events"], "ok");
+
+        let health = HealthState::new(Duration::ZERO);
+        for check in Check::ALL {
+            health.set(check, Ok(()));
+        }
+        health.record_poll();
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(!health.readiness().0);
+    }
+
+    #[test]
+    fn test_readiness_per_chain() {
+        let health = HealthState::new(Duration::from_secs(60));
+        let chains = [health.for_chain(84532), health.for_chain(8453)];
+        let (ready, status) = health.readiness();
+        assert!(!ready);
+        assert_eq!(status["checks"]["rpc@84532"], "pending");
+        assert_eq!(status["checks"]["events@8453"], "pending");
+        assert!(status["checks"].get("rpc").is_none());
+
+        for check in Check::ALL {
+            chains[0].set(check, Ok(()));
+        }
+        chains[0].record_poll();
+        assert!(!health.readiness().0);
+
+        for check in Check::ALL {
+            chains[1].set(check, Ok(()));
+        }
+        chains[1].record_poll();
+        assert!(health.readiness().0);
+
+        chains[1].set(Check::Rpc, Err(eyre!("RPC is not responding")));
+        let (ready, status) = health.readiness();
+        assert!(!ready);
+        assert_eq!(status["checks"]["rpc@8453"], "RPC is not responding");
+        assert_eq!(status["checks"]["rpc@84532"], "ok");
+    }
+}
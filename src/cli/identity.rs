@@ -0,0 +1,150 @@
+use crate::{
+    contracts::{OracleKind, TaskStatus},
+    metrics::TaskLabels,
+    DriaOracle,
+    OracleCoordinator::StatusUpdate,
+};
+use alloy::primitives::Address;
+use eyre::{eyre, Result};
+use std::cell::Cell;
+
+/// An identity that the node serves tasks as, i.e. a wallet along with its oracle kinds.
+pub struct Identity {
+    pub node: DriaOracle,
+    pub kinds: Vec<OracleKind>,
+}
+
+impl Identity {
+    /// Creates the identity of the given node, with the given kinds or its registrations.
+    ///
+    /// If kinds are not given, the kinds that the node is registered as are used,
+    /// otherwise the node must be registered as the given kinds, except in dry-run mode.
+    /// Validators must also be whitelisted, except in dry-run mode.
+    pub async fn new(node: DriaOracle, kinds: &[OracleKind]) -> Result<Self> {
+        let address = node.address();
+        let mut identity_kinds = Vec::new();
+        if kinds.is_empty() {
+            log::debug!("No kinds provided. Checking registrations of {}.", address);
+            for kind in [OracleKind::Generator, OracleKind::Validator] {
+                if node.is_registered(kind).await? {
+                    identity_kinds.push(kind);
+                }
+            }
+
+            if identity_kinds.is_empty() {
+                return Err(eyre!(
+                    "{} is not registered as any type of oracle.",
+                    address
+                ));
+            }
+        } else if node.config.dry_run {
+            identity_kinds = kinds.to_vec();
+        } else {
+            for kind in kinds {
+                if !node.is_registered(*kind).await? {
+                    return Err(eyre!("{} needs to register as {} first.", address, kind));
+                }
+            }
+            identity_kinds = kinds.to_vec();
+        }
+
+        if identity_kinds.contains(&OracleKind::Validator)
+            && !node.config.dry_run
+            && !node.is_whitelisted(address).await?
+        {
+            return Err(eyre!("{} is not whitelisted in the registry.", address));
+        }
+
+        Ok(Self {
+            node,
+            kinds: identity_kinds,
+        })
+    }
+}
+
+/// Identities of the node, which share the same events & models.
+pub struct Identities {
+    identities: Vec<Identity>,
+    /// Index to start looking for a responder from, so that tasks are spread among the identities.
+    next: Cell<usize>,
+}
+
+impl Identities {
+    /// Creates the identities, where the first one is the main identity of the node.
+    pub fn new(identities: Vec<Identity>) -> Self {
+        assert!(!identities.is_empty(), "there must be an identity");
+        Self {
+            identities,
+            next: Cell::new(0),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Identity> {
+        self.identities.iter()
+    }
+
+    /// Returns all the kinds that are served by any identity.
+    pub fn kinds(&self) -> Vec<OracleKind> {
+        [OracleKind::Generator, OracleKind::Validator]
+            .into_iter()
+            .filter(|kind| self.iter().any(|identity| identity.kinds.contains(kind)))
+            .collect()
+    }
+
+    /// Chooses the identity to handle the given event with, in turns among the ones that serve its kind.
+    ///
+    /// A validator is not chosen for a task that another identity has generated for, so that
+    /// identities do not validate each other; in which case `None` is returned if there is no
+    /// other validator. Events that no identity serves go to the main identity, to be ignored there.
+    pub async fn choose(&self, event: &StatusUpdate) -> Result<Option<&Identity>> {
+        let status = TaskStatus::try_from(event.statusAfter)?;
+        let kind = match status {
+            TaskStatus::PendingGeneration => OracleKind::Generator,
+            TaskStatus::PendingValidation => OracleKind::Validator,
+            _ => return Ok(Some(&self.identities[0])),
+        };
+
+        let mut candidates = self
+            .iter()
+            .filter(|identity| identity.kinds.contains(&kind))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Ok(Some(&self.identities[0]));
+        }
+
+        if kind == OracleKind::Validator && self.identities.len() > 1 {
+            let responders = self.identities[0]
+                .node
+                .get_task_responses(event.taskId)
+                .await?
+                .into_iter()
+                .map(|response| response.responder)
+                .collect::<Vec<_>>();
+            candidates.retain(|identity| !self.has_sibling_in(identity, &responders));
+
+            if candidates.is_empty() {
+                log::info!(
+                    "Not validating task {} as it is generated by our own identities.",
+                    event.taskId
+                );
+                let labels =
+                    TaskLabels::new(self.identities[0].node.chain_id, status, &event.protocol);
+                labels.seen();
+                labels.ignored();
+                return Ok(None);
+            }
+        }
+
+        let next = self.next.get();
+        self.next.set(next.wrapping_add(1));
+        Ok(Some(candidates[next % candidates.len()]))
+    }
+
+    /// Returns whether any of the given addresses belongs to an identity other than the given one.
+    fn has_sibling_in(&self, identity: &Identity, addresses: &[Address]) -> bool {
+        let address = identity.node.address();
+        self.iter()
+            .map(|sibling| sibling.node.address())
+            .any(|sibling| sibling != address && addresses.contains(&sibling))
+    }
+}
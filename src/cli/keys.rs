@@ -0,0 +1,97 @@
+use super::parsers::parse_secret_key;
+use crate::configurations::{
+    read_secret_file, signer_from_keystore, signer_from_mnemonic, signer_from_secret_key,
+    SecretKey, DEFAULT_DERIVATION_PATH,
+};
+use alloy::signers::local::PrivateKeySigner;
+use clap::Args;
+use eyre::{eyre, Context, Result};
+use std::{io::IsTerminal, path::PathBuf};
+use zeroize::Zeroizing;
+
+/// Sources of the wallet's keys, where at least one of them must be given.
+///
+/// The keys are taken in the order of secret keys, keystores and mnemonic derivations;
+/// the first one is the main identity of the node, and the others are served as other identities by `start`.
+#[derive(Args)]
+#[group(skip)]
+pub struct WalletArgs {
+    /// Ethereum wallet's secret (private) keys as hexadecimal strings.
+    #[arg(short, long = "secret-key", env = "SECRET_KEY", value_parser = parse_secret_key, value_delimiter = ',', hide_env_values = true)]
+    secret_keys: Vec<SecretKey>,
+
+    /// Ethereum V3 JSON keystore files, all decrypted with the same password.
+    #[arg(long = "keystore", env = "KEYSTORE", value_delimiter = ',')]
+    keystores: Vec<PathBuf>,
+
+    /// File that contains the password of the keystores, otherwise `KEYSTORE_PASSWORD` is used or the password is prompted.
+    #[arg(long, env = "KEYSTORE_PASSWORD_FILE")]
+    keystore_password_file: Option<PathBuf>,
+
+    /// Password of the keystores, prefer the password file or the prompt as this may end up in the shell history.
+    #[arg(long, env = "KEYSTORE_PASSWORD", hide_env_values = true)]
+    keystore_password: Option<String>,
+
+    /// File that contains a BIP-39 mnemonic.
+    #[arg(long, env = "MNEMONIC_FILE")]
+    mnemonic_file: Option<PathBuf>,
+
+    /// Derivation paths of the keys from the mnemonic.
+    #[arg(long = "derivation-path", env = "DERIVATION_PATH", value_delimiter = ',', default_value = DEFAULT_DERIVATION_PATH)]
+    derivation_paths: Vec<String>,
+}
+
+impl WalletArgs {
+    /// Loads the signers from the given sources, zeroizing the key material that is read.
+    ///
+    /// The key material is zeroized when dropped, so the keys that are not used yet are zeroized
+    /// as well if one of them fails.
+    pub fn into_signers(mut self) -> Result<Vec<PrivateKeySigner>> {
+        let mut signers = Vec::new();
+
+        for secret_key in std::mem::take(&mut self.secret_keys) {
+            signers.push(signer_from_secret_key(secret_key)?);
+        }
+
+        if !self.keystores.is_empty() {
+            let password = self.keystore_password()?;
+            for keystore in &self.keystores {
+                signers.push(signer_from_keystore(keystore, &password)?);
+            }
+        }
+
+        if let Some(mnemonic_file) = &self.mnemonic_file {
+            let phrase = read_secret_file(mnemonic_file).wrap_err("could not read mnemonic")?;
+            for derivation_path in &self.derivation_paths {
+                signers.push(signer_from_mnemonic(&phrase, derivation_path)?);
+            }
+        }
+
+        if signers.is_empty() {
+            return Err(eyre!(
+                "No wallet is given, use SECRET_KEY, KEYSTORE or MNEMONIC_FILE."
+            ));
+        }
+
+        Ok(signers)
+    }
+
+    /// Returns the password of the keystores from the password file, `KEYSTORE_PASSWORD`, or the prompt in that order.
+    fn keystore_password(&mut self) -> Result<Zeroizing<String>> {
+        if let Some(path) = &self.keystore_password_file {
+            return read_secret_file(path).wrap_err("could not read keystore password");
+        }
+        if let Some(password) = self.keystore_password.take() {
+            return Ok(Zeroizing::new(password));
+        }
+        if !std::io::stdin().is_terminal() {
+            return Err(eyre!(
+                "Keystore password is not given, use KEYSTORE_PASSWORD_FILE or KEYSTORE_PASSWORD."
+            ));
+        }
+
+        rpassword::prompt_password("Keystore password: ")
+            .map(Zeroizing::new)
+            .wrap_err("could not read keystore password")
+    }
+}
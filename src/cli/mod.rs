@@ -1,15 +1,42 @@
 mod commands;
-use commands::Commands;
+use commands::{Commands, RunOptions, SharedModelConfig};
 
 mod parsers;
 use parsers::*;
 
-use crate::{DriaOracle, DriaOracleConfig};
-use alloy::{eips::BlockNumberOrTag, primitives::B256};
+mod checkpoint;
+
+mod queue;
+
+mod retry;
+
+mod server;
+
+mod health;
+use health::HealthState;
+
+mod control;
+
+mod identity;
+
+mod keys;
+use keys::WalletArgs;
+
+use crate::{
+    configurations::DEFAULT_LOG_CHUNK_SIZE, DriaOracle, DriaOracleConfig, FeePolicy, PriorityFee,
+    ProfitabilityGate, ProtocolFilter, ReplacementPolicy,
+};
+use alloy::{eips::BlockNumberOrTag, network::EthereumWallet};
 use clap::Parser;
-use eyre::{Context, Result};
+use eyre::{eyre, Context, Result};
+use futures_util::future::join_all;
 use reqwest::Url;
+use std::{rc::Rc, time::Duration};
 use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+/// Interval to update the balance metrics.
+const METRICS_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -18,13 +45,49 @@ struct Cli {
     #[command(subcommand)]
     command: Commands,
 
-    /// RPC URL of the Ethereum node.
-    #[arg(short, long, env = "RPC_URL", value_parser = parse_url)]
-    rpc_url: Url,
+    /// RPC URLs of the Ethereum node by their priorities, where the ones after the first are fallbacks.
+    /// Required by all commands except `ctl` & `journal`.
+    #[arg(short, long = "rpc-url", env = "RPC_URL", value_parser = parse_url, value_delimiter = ',')]
+    rpc_urls: Vec<Url>,
+
+    #[command(flatten)]
+    wallet: WalletArgs,
+
+    /// Maximum number of blocks to query at once when fetching logs.
+    #[arg(long, env = "LOG_CHUNK_SIZE", default_value_t = DEFAULT_LOG_CHUNK_SIZE)]
+    log_chunk_size: u64,
 
-    /// Ethereum wallet's secret (private) key.
-    #[arg(short, long, env = "SECRET_KEY", value_parser = parse_secret_key)]
-    secret_key: B256,
+    /// Maximum fee per gas of the transactions in gwei, the estimated fees are capped to this.
+    #[arg(long, env = "MAX_FEE_PER_GAS", value_parser = parse_gwei)]
+    max_fee_per_gas: Option<u128>,
+
+    /// Priority fee per gas of the transactions, either in gwei (e.g. `1.5`) or as a percentile of the recent blocks (e.g. `p50`).
+    #[arg(long, env = "PRIORITY_FEE", value_parser = parse_priority_fee)]
+    priority_fee: Option<PriorityFee>,
+
+    /// Base fee plus priority fee per gas in gwei, above which transactions are not sent and tasks are deferred.
+    #[arg(long, env = "FEE_CEILING", value_parser = parse_gwei)]
+    fee_ceiling: Option<u128>,
+
+    /// Seconds to wait for a transaction to be mined before replacing it with bumped fees, disabled if not given.
+    #[arg(long, env = "TX_REPLACE_AFTER")]
+    replace_after: Option<u64>,
+
+    /// Percentage to bump the fees by for each replacement.
+    #[arg(long, env = "TX_FEE_BUMP", default_value_t = 20)]
+    fee_bump: u64,
+
+    /// Maximum number of replacements of a transaction.
+    #[arg(long, env = "TX_MAX_REPLACEMENTS", default_value_t = 3)]
+    max_replacements: u32,
+
+    /// Cancel a transaction with a zero-value transfer to self once there are no replacements left.
+    #[arg(long, env = "TX_CANCEL_STUCK")]
+    cancel_stuck: bool,
+
+    /// Send transactions without simulating them with `eth_call` first, saving a round-trip per transaction.
+    #[arg(long, env = "NO_SIMULATION")]
+    no_simulation: bool,
 }
 
 /// Main CLI entry point.
@@ -33,13 +96,51 @@ pub async fn cli() -> Result<()> {
     // so we can do the node setup after this line
     let cli = Cli::parse();
 
-    // store cli-parsed options
-    let rpc_url = cli.rpc_url;
-    let secret_key = cli.secret_key;
+    // control commands only talk to a running node
+    if let Commands::Ctl { addr, command } = cli.command {
+        return control::run_ctl(addr, command).await;
+    }
+
+    // the journal is local, so it does not need the RPC or the keys
+    if let Commands::Journal {
+        task_id,
+        limit,
+        data_dir,
+        chain_id,
+        coordinator,
+    } = cli.command
+    {
+        return commands::display_journal(&data_dir, chain_id, coordinator, task_id, limit);
+    }
+
+    // the other commands talk to the chain, so they need an RPC
+    let mut rpc_urls = cli.rpc_urls;
+    if rpc_urls.is_empty() {
+        return Err(eyre!("RPC_URL is required for this command."));
+    }
+    let rpc_url = rpc_urls.remove(0);
+
+    // load the keys, where the ones after the first are other identities
+    let mut signers = cli.wallet.into_signers()?;
+    let signer = signers.remove(0);
 
     // create node
-    let config = DriaOracleConfig::new(&secret_key, rpc_url)
-        .wrap_err("could not create oracle configuration")?;
+    let config = DriaOracleConfig::from_signer(signer, rpc_url)
+        .with_fallback_rpc_urls(rpc_urls)
+        .with_log_chunk_size(cli.log_chunk_size)
+        .with_fee_policy(FeePolicy {
+            max_fee_per_gas: cli.max_fee_per_gas,
+            priority_fee: cli.priority_fee.unwrap_or_default(),
+            fee_ceiling: cli.fee_ceiling,
+        })
+        .with_replacement(cli.replace_after.map(|secs| ReplacementPolicy {
+            timeout: Duration::from_secs(secs),
+            fee_bump_percent: cli.fee_bump,
+            max_replacements: cli.max_replacements,
+            cancel: cli.cancel_stuck,
+        }))
+        .with_simulation(!cli.no_simulation)
+        .with_dry_run(matches!(cli.command, Commands::Start { dry_run: true, .. }));
     let node = DriaOracle::new(config)
         .await
         .wrap_err("could not create oracle node")?;
@@ -65,6 +166,25 @@ pub async fn cli() -> Result<()> {
             kinds,
             models,
             from,
+            max_tasks,
+            task_timeout,
+            data_dir,
+            reset_checkpoint,
+            confirmations,
+            max_retries,
+            retry_delay,
+            grace_period,
+            include_protocols,
+            exclude_protocols,
+            min_profit_margin,
+            model_prices,
+            metrics,
+            health: health_addr,
+            max_poll_age,
+            no_journal,
+            dry_run: _,
+            control,
+            chains,
         } => {
             let token = CancellationToken::new();
 
@@ -74,21 +194,172 @@ pub async fn cli() -> Result<()> {
                 wait_for_termination(termination_token).await.unwrap();
             });
 
-            // launch node
-            node.run_oracle(
-                kinds,
-                models,
-                from.unwrap_or(BlockNumberOrTag::Latest),
-                token,
-            )
-            .await?;
+            // serve metrics & health checks if enabled, where the metrics address serves
+            // the health checks as well, and the health address serves them only
+            let health = (metrics.is_some() || health_addr.is_some())
+                .then(|| HealthState::new(Duration::from_secs(max_poll_age)));
+            let servers = metrics.map(|addr| (addr, true)).into_iter().chain(
+                health_addr
+                    .filter(|addr| metrics != Some(*addr))
+                    .map(|addr| (addr, false)),
+            );
+            for (addr, with_metrics) in servers {
+                let health = health.clone().expect("health is set with any address");
+                let server_token = token.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = server::serve(addr, health, with_metrics, server_token).await {
+                        log::error!("HTTP server at {} stopped: {:?}", addr, e);
+                    }
+                });
+            }
+
+            // connect to the other chains, with the same wallet & settings as the first one
+            let mut nodes = vec![node];
+            for chain in chains {
+                let mut rpc_urls = chain.rpc_urls;
+                let rpc_url = rpc_urls.remove(0);
+                let config = nodes[0]
+                    .config
+                    .clone()
+                    .with_rpc_url(rpc_url)
+                    .with_fallback_rpc_urls(rpc_urls)
+                    .with_addresses(chain.addresses);
+                let chain_node = DriaOracle::new(config)
+                    .await
+                    .wrap_err("could not create oracle node")?;
+                if nodes
+                    .iter()
+                    .any(|node| node.chain_id == chain_node.chain_id)
+                {
+                    return Err(eyre!(
+                        "Chain {} is given more than once.",
+                        chain_node.chain_id
+                    ));
+                }
+                log::info!("{}", chain_node);
+                log::info!("{}", chain_node.addresses);
+                nodes.push(chain_node);
+            }
+
+            // serve the control API if enabled, its requests are handled within the event loop
+            // of the first chain, while the models it sets are shared by all chains
+            let mut control = match control {
+                Some(addr) => {
+                    let (sender, receiver) = tokio::sync::mpsc::channel(8);
+                    let control_token = token.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = control::serve(addr, sender, control_token).await {
+                            log::error!("Control API stopped: {:?}", e);
+                        }
+                    });
+                    Some(receiver)
+                }
+                None => None,
+            };
+
+            // prepare the models & check their services once, for all chains
+            let model_config =
+                SharedModelConfig::new(Rc::new(DriaOracle::prepare_models(models).await?));
+            let model_config = &model_config;
+
+            let options = RunOptions {
+                max_tasks,
+                task_timeout: Duration::from_secs(task_timeout),
+                data_dir,
+                reset_checkpoint,
+                confirmations,
+                max_retries,
+                retry_delay: Duration::from_secs(retry_delay),
+                grace_period: Duration::from_secs(grace_period),
+                protocols: ProtocolFilter::new(include_protocols, exclude_protocols),
+                profitability: min_profit_margin
+                    .map(|margin| ProfitabilityGate::new(margin, model_prices)),
+                health: None,
+                journal: None,
+                identities: signers.into_iter().map(EthereumWallet::from).collect(),
+            };
 
-            // wait for handle
-            if let Err(e) = termination_handle.await {
-                log::error!("Error in termination handler: {}", e);
+            // launch an event loop per chain, where each has its own checkpoint & journal;
+            // if one of them fails, the others are stopped as well
+            let mut runs = Vec::new();
+            for (idx, node) in nodes.iter().enumerate() {
+                let mut options = options.clone();
+                options.health = match &health {
+                    Some(health) if nodes.len() > 1 => Some(health.for_chain(node.chain_id)),
+                    health => health.clone(),
+                };
+                // open the journal unless disabled
+                if !no_journal {
+                    options.journal = Some(node.open_journal(&options.data_dir)?);
+                }
+                // the starting block is only meaningful for the first chain
+                let from = if idx == 0 { from } else { None };
+                let control = if idx == 0 { control.take() } else { None };
+                let kinds = kinds.clone();
+                let run_token = token.clone();
+
+                let span = tracing::info_span!("chain", chain = node.chain_id);
+                runs.push(
+                    async move {
+                        let result = node
+                            .run_oracle(
+                                kinds,
+                                model_config,
+                                from,
+                                options,
+                                control,
+                                run_token.clone(),
+                            )
+                            .await
+                            .wrap_err_with(|| {
+                                format!("could not run oracle on chain {}", node.chain_id)
+                            });
+                        if result.is_err() {
+                            run_token.cancel();
+                        }
+                        result
+                    }
+                    .instrument(span),
+                );
+            }
+            let run = async {
+                join_all(runs)
+                    .await
+                    .into_iter()
+                    .collect::<Result<Vec<()>>>()
+            };
+
+            if metrics.is_some() {
+                // balances of each identity are reported alongside the node, until it stops
+                let reporters = nodes
+                    .iter()
+                    .flat_map(|node| {
+                        let identities = options
+                            .identities
+                            .iter()
+                            .map(|wallet| node.connect(wallet.clone()));
+                        std::iter::once(node.clone()).chain(identities)
+                    })
+                    .collect::<Vec<_>>();
+                let report = join_all(
+                    reporters
+                        .iter()
+                        .map(|node| node.report_metrics(METRICS_INTERVAL)),
+                );
+                tokio::select! {
+                    result = run => { result?; }
+                    _ = report => {},
+                }
+            } else {
+                run.await?;
             }
+
+            // the node has stopped, so we dont need to wait for another signal
+            termination_handle.abort();
         }
+        Commands::Ctl { .. } => unreachable!("control commands are handled before"),
         Commands::View { task_id } => node.view_task(task_id).await?,
+        Commands::Journal { .. } => unreachable!("journal is handled before"),
         Commands::Tasks { from, to } => {
             node.view_task_events(
                 from.unwrap_or(BlockNumberOrTag::Earliest),
@@ -121,6 +392,9 @@ pub async fn cli() -> Result<()> {
 }
 
 /// Waits for various termination signals, and cancels the given token when the signal is received.
+///
+/// The node is expected to shut down gracefully after the cancellation, and if a
+/// second signal is received in the meantime, the process exits immediately.
 async fn wait_for_termination(cancellation: CancellationToken) -> Result<()> {
     #[cfg(unix)]
     {
@@ -138,15 +412,23 @@ async fn wait_for_termination(cancellation: CancellationToken) -> Result<()> {
         };
 
         cancellation.cancel();
+        log::info!("Terminating the application, send the signal again to force exit.");
+
+        // a second signal means that we dont want to wait for the tasks in progress
+        tokio::select! {
+            _ = sigterm.recv() => log::warn!("Recieved SIGTERM again"),
+            _ = sigint.recv() => log::warn!("Recieved SIGINT again"),
+        };
+        log::warn!("Forcing exit.");
+        std::process::exit(1);
     }
 
     #[cfg(not(unix))]
     {
         log::error!("No signal handling for this platform: {}", env::consts::OS);
         cancellation.cancel();
-    }
-
-    log::info!("Terminating the application...");
+        log::info!("Terminating the application...");
 
-    Ok(())
+        Ok(())
+    }
 }
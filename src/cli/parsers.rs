@@ -1,22 +1,59 @@
-use alloy::{eips::BlockNumberOrTag, hex::FromHex, primitives::B256};
+use crate::{
+    configurations::{secret_key_from_hex, SecretKey},
+    ContractAddresses, PriorityFee, ProtocolPattern,
+};
+use alloy::{
+    eips::BlockNumberOrTag,
+    primitives::{
+        utils::{parse_units, ParseUnits},
+        Address,
+    },
+};
 use dkn_workflows::Model;
 use eyre::{eyre, Result};
 use reqwest::Url;
-use std::str::FromStr;
+use std::{net::SocketAddr, str::FromStr};
 
 /// `value_parser` to parse a `str` to `OracleKind`.
 pub fn parse_model(value: &str) -> Result<Model> {
     Model::try_from(value.to_string()).map_err(|e| eyre!(e))
 }
 
+/// `value_parser` to parse a `model=price` pair, where price is a non-negative number.
+pub fn parse_model_price(value: &str) -> Result<(Model, f64)> {
+    let (model, price) = value
+        .split_once('=')
+        .ok_or_else(|| eyre!("Expected a model=price pair: {}", value))?;
+    let model = parse_model(model.trim())?;
+    let price = price.trim().parse::<f64>()?;
+    if !price.is_finite() || price < 0.0 {
+        return Err(eyre!("Invalid price: {}", price));
+    }
+
+    Ok((model, price))
+}
+
+/// `value_parser` to parse a `str` to `ProtocolPattern`.
+pub fn parse_protocol_pattern(value: &str) -> Result<ProtocolPattern> {
+    ProtocolPattern::from_str(value)
+}
+
 /// `value_parser` to parse a `str` to `Url`.
+///
+/// If the value is not a URL, it is treated as a path (e.g. to an IPC socket) and parsed as a `file://` URL.
 pub fn parse_url(value: &str) -> Result<Url> {
-    Url::parse(value).map_err(Into::into)
+    match Url::parse(value) {
+        Ok(url) => Ok(url),
+        Err(_) => {
+            let path = std::path::absolute(value)?;
+            Url::from_file_path(&path).map_err(|_| eyre!("Invalid URL or path: {}", value))
+        }
+    }
 }
 
-/// `value_parser` to parse a hexadecimal `str` to 256-bit type `B256`.
-pub fn parse_secret_key(value: &str) -> Result<B256> {
-    B256::from_hex(value).map_err(Into::into)
+/// `value_parser` to parse a hexadecimal `str` to a [`SecretKey`], which is zeroized when dropped.
+pub fn parse_secret_key(value: &str) -> Result<SecretKey> {
+    secret_key_from_hex(value)
 }
 
 /// `value parser` to parse a `str` to `BlockNumberOrTag`
@@ -30,9 +67,95 @@ pub fn parse_block_number_or_tag(value: &str) -> Result<BlockNumberOrTag> {
         Err(_) => BlockNumberOrTag::from_str(value).map_err(Into::into),
     }
 }
+
+/// `value_parser` to parse a loopback `SocketAddr`, for the endpoints that are not authenticated.
+pub fn parse_loopback_addr(value: &str) -> Result<SocketAddr> {
+    let addr = SocketAddr::from_str(value)?;
+    if !addr.ip().is_loopback() {
+        return Err(eyre!("Expected a loopback address: {}", value));
+    }
+
+    Ok(addr)
+}
+
+/// `value_parser` to parse a non-negative gwei amount (e.g. `1.5`) to wei.
+pub fn parse_gwei(value: &str) -> Result<u128> {
+    match parse_units(value.trim(), "gwei")? {
+        ParseUnits::U256(amount) => {
+            u128::try_from(amount).map_err(|_| eyre!("Amount is too large: {}", value))
+        }
+        ParseUnits::I256(_) => Err(eyre!("Expected a non-negative amount: {}", value)),
+    }
+}
+
+/// `value_parser` to parse a priority fee, either as a percentile of the recent blocks
+/// such as `p50`, or as a fixed amount in gwei such as `1.5`.
+pub fn parse_priority_fee(value: &str) -> Result<PriorityFee> {
+    match value.trim().strip_prefix('p') {
+        Some(percentile) => {
+            let percentile = percentile.parse::<f64>()?;
+            if !(0.0..=100.0).contains(&percentile) {
+                return Err(eyre!("Percentile must be within 0 and 100: {}", value));
+            }
+            Ok(PriorityFee::Percentile(percentile))
+        }
+        None => parse_gwei(value).map(PriorityFee::Fixed),
+    }
+}
+
+/// A chain to serve, with its RPC URLs by their priorities & optional contract addresses.
+#[derive(Debug, Clone)]
+pub struct ChainSpec {
+    pub rpc_urls: Vec<Url>,
+    pub addresses: Option<ContractAddresses>,
+}
+
+/// `value_parser` to parse a `<rpc-url>[,<fallback-rpc-url>...][#<token>,<registry>,<coordinator>]`
+/// string to `ChainSpec`, where the URLs are parsed as in [`parse_url`].
+pub fn parse_chain(value: &str) -> Result<ChainSpec> {
+    let (urls, addresses) = match value.split_once('#') {
+        Some((urls, addresses)) => (urls, Some(addresses)),
+        None => (value, None),
+    };
+
+    let rpc_urls = urls
+        .split(',')
+        .map(|url| parse_url(url.trim()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let addresses = addresses
+        .map(|addresses| {
+            let addresses = addresses
+                .split(',')
+                .map(|address| Address::from_str(address.trim()))
+                .collect::<Result<Vec<_>, _>>()?;
+            match addresses[..] {
+                [token, registry, coordinator] => Ok(ContractAddresses {
+                    token,
+                    registry,
+                    coordinator,
+                }),
+                _ => Err(eyre!(
+                    "Expected token, registry & coordinator addresses: {}",
+                    value
+                )),
+            }
+        })
+        .transpose()?;
+
+    Ok(ChainSpec {
+        rpc_urls,
+        addresses,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloy::{
+        hex::FromHex,
+        primitives::{address, B256},
+    };
 
     #[test]
     fn test_parse_model() {
@@ -43,6 +166,19 @@ mod tests {
         assert_eq!(model, Model::try_from(model_str.to_string()).unwrap());
     }
 
+    #[test]
+    fn test_parse_model_price() {
+        let (model, price) = parse_model_price("llama3.1:latest=0.0002").unwrap();
+        assert_eq!(
+            model,
+            Model::try_from("llama3.1:latest".to_string()).unwrap()
+        );
+        assert_eq!(price, 0.0002);
+
+        assert!(parse_model_price("llama3.1:latest").is_err());
+        assert!(parse_model_price("llama3.1:latest=-1").is_err());
+    }
+
     #[test]
     fn test_parse_url() {
         let url_str = "https://example.com";
@@ -52,6 +188,16 @@ mod tests {
         assert_eq!(url, Url::parse(url_str).unwrap());
     }
 
+    #[test]
+    fn test_parse_url_ipc() {
+        let path_str = "/tmp/anvil.ipc";
+        let result = parse_url(path_str);
+        assert!(result.is_ok());
+        let url = result.unwrap();
+        assert_eq!(url.scheme(), "file");
+        assert_eq!(url.to_file_path().unwrap().to_str().unwrap(), path_str);
+    }
+
     #[test]
     fn test_parse_secret_key() {
         let hex_str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
@@ -59,7 +205,7 @@ mod tests {
         assert!(result.is_ok());
 
         let secret_key = result.unwrap();
-        assert_eq!(secret_key, B256::from_hex(hex_str).unwrap());
+        assert_eq!(*secret_key, B256::from_hex(hex_str).unwrap().0);
     }
 
     #[test]
@@ -85,4 +231,51 @@ mod tests {
             BlockNumberOrTag::from_str(block_hex_str).unwrap()
         );
     }
+
+    #[test]
+    fn test_parse_loopback_addr() {
+        assert!(parse_loopback_addr("127.0.0.1:9091").is_ok());
+        assert!(parse_loopback_addr("[::1]:9091").is_ok());
+        assert!(parse_loopback_addr("0.0.0.0:9091").is_err());
+    }
+
+    #[test]
+    fn test_parse_priority_fee() {
+        assert_eq!(parse_gwei("1.5").unwrap(), 1_500_000_000);
+        assert!(parse_gwei("-1").is_err());
+
+        assert_eq!(
+            parse_priority_fee("p50").unwrap(),
+            PriorityFee::Percentile(50.0)
+        );
+        assert_eq!(
+            parse_priority_fee("2").unwrap(),
+            PriorityFee::Fixed(2_000_000_000)
+        );
+        assert!(parse_priority_fee("p101").is_err());
+    }
+
+    #[test]
+    fn test_parse_chain() {
+        let chain = parse_chain("https://a.example, https://b.example").unwrap();
+        assert_eq!(chain.rpc_urls.len(), 2);
+        assert!(chain.addresses.is_none());
+
+        let chain = parse_chain(
+            "wss://a.example#0x4200000000000000000000000000000000000006,\
+             0x408d245a853137e44a2465d5c66061f97582eae9,\
+             0x13f977bde221b470d3ae055cde7e1f84debfe202",
+        )
+        .unwrap();
+        assert_eq!(chain.rpc_urls, vec![Url::parse("wss://a.example").unwrap()]);
+        let addresses = chain.addresses.unwrap();
+        assert_eq!(
+            addresses.coordinator,
+            address!("13f977bde221b470d3ae055cde7e1f84debfe202")
+        );
+
+        assert!(
+            parse_chain("https://a.example#0x4200000000000000000000000000000000000006").is_err()
+        );
+    }
 }
@@ -0,0 +1,264 @@
+use crate::OracleCoordinator::StatusUpdate;
+use alloy::{
+    primitives::{B256, U256},
+    rpc::types::Log,
+};
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+/// Number of recently seen events to remember for de-duplication.
+const SEEN_CAPACITY: usize = 10_000;
+
+/// Identifies a log within the chain, by its block hash and log index.
+pub type LogKey = (Option<B256>, Option<u64>);
+
+/// Returns the key of a log, which is the same for a log and its `removed` counterpart.
+pub fn log_key(log: &Log) -> LogKey {
+    (log.block_hash, log.log_index)
+}
+
+/// A queue of task events to be processed.
+///
+/// Events first wait until they have the required number of confirmations,
+/// and are then moved to the ready queue to be picked up by the workers.
+///
+/// An event that is seen more than once, e.g. both from a backfill and the live stream,
+/// is only queued once w.r.t its `(taskId, statusAfter)` pair.
+pub struct TaskQueue {
+    /// Number of blocks to wait on top of an event's block before it is processed.
+    confirmations: u64,
+    /// Events that are waiting for confirmations.
+    unconfirmed: Vec<(StatusUpdate, Log)>,
+    /// Events that are ready to be processed, in order.
+    ready: VecDeque<(StatusUpdate, Log)>,
+    /// Recently seen events as `(taskId, statusAfter)` pairs.
+    seen: HashSet<(U256, u8)>,
+    /// Order of the seen events, so that the oldest ones can be forgotten.
+    seen_order: VecDeque<(U256, u8)>,
+}
+
+impl TaskQueue {
+    /// Creates a new queue with the given confirmation depth.
+    pub fn new(confirmations: u64) -> Self {
+        Self {
+            confirmations,
+            unconfirmed: Vec::new(),
+            ready: VecDeque::new(),
+            seen: HashSet::new(),
+            seen_order: VecDeque::new(),
+        }
+    }
+
+    /// Adds an event to the queue, it is ready right away if no confirmations are required.
+    ///
+    /// Returns `false` if the event has been seen before, in which case it is not added.
+    pub fn push(&mut self, event: StatusUpdate, log: Log) -> bool {
+        let key = (event.taskId, event.statusAfter);
+        if !self.seen.insert(key) {
+            return false;
+        }
+        self.seen_order.push_back(key);
+        if self.seen_order.len() > SEEN_CAPACITY {
+            if let Some(oldest) = self.seen_order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+
+        self.push_unconfirmed(event, log);
+        true
+    }
+
+    /// Adds an event to the queue without checking if it has been seen before.
+    pub fn push_unconfirmed(&mut self, event: StatusUpdate, log: Log) {
+        if self.confirmations == 0 {
+            self.ready.push_back((event, log));
+        } else {
+            self.unconfirmed.push((event, log));
+        }
+    }
+
+    /// Forgets that an event has been seen, so that it can be queued again.
+    pub fn forget(&mut self, event: &StatusUpdate) {
+        let key = (event.taskId, event.statusAfter);
+        if self.seen.remove(&key) {
+            self.seen_order.retain(|k| *k != key);
+        }
+    }
+
+    /// Adds an event to the ready queue, regardless of its confirmations.
+    pub fn push_ready(&mut self, event: StatusUpdate, log: Log) {
+        self.ready.push_back((event, log));
+    }
+
+    /// Returns the next ready event.
+    pub fn pop(&mut self) -> Option<(StatusUpdate, Log)> {
+        self.ready.pop_front()
+    }
+
+    /// Returns the next ready event that satisfies the predicate,
+    /// the events that are skipped keep their order.
+    pub fn pop_where(
+        &mut self,
+        predicate: impl Fn(&StatusUpdate) -> bool,
+    ) -> Option<(StatusUpdate, Log)> {
+        let idx = self.ready.iter().position(|(event, _)| predicate(event))?;
+        self.ready.remove(idx)
+    }
+
+    /// Iterates over all the queued events, confirmed or not.
+    pub fn iter(&self) -> impl Iterator<Item = &(StatusUpdate, Log)> {
+        self.unconfirmed.iter().chain(self.ready.iter())
+    }
+
+    /// Number of events that are ready to be processed.
+    pub fn len(&self) -> usize {
+        self.ready.len()
+    }
+
+    /// Returns `true` if there are no events ready to be processed.
+    pub fn is_empty(&self) -> bool {
+        self.ready.is_empty()
+    }
+
+    /// Returns `true` if there are events waiting for confirmations.
+    pub fn has_unconfirmed(&self) -> bool {
+        !self.unconfirmed.is_empty()
+    }
+
+    /// Number of blocks to wait on top of an event's block before it is processed.
+    pub fn confirmations(&self) -> u64 {
+        self.confirmations
+    }
+
+    /// Returns the block numbers of the events that are waiting for confirmations.
+    pub fn unconfirmed_blocks(&self) -> BTreeSet<u64> {
+        self.unconfirmed
+            .iter()
+            .filter_map(|(_, log)| log.block_number)
+            .collect()
+    }
+
+    /// Takes the unconfirmed events that have enough confirmations w.r.t the given head block.
+    ///
+    /// The caller is expected to check if they are still in the canonical chain
+    /// before adding them back with [`TaskQueue::push_ready`].
+    pub fn take_confirmed(&mut self, head: u64) -> Vec<(StatusUpdate, Log)> {
+        let confirmations = self.confirmations;
+        let (confirmed, unconfirmed) =
+            std::mem::take(&mut self.unconfirmed)
+                .into_iter()
+                .partition(|(_, log)| {
+                    log.block_number
+                        .map(|block_number| block_number.saturating_add(confirmations) <= head)
+                        .unwrap_or(true)
+                });
+        self.unconfirmed = unconfirmed;
+
+        confirmed
+    }
+
+    /// Removes the event with the given log key, whether it is confirmed or not.
+    pub fn remove(&mut self, key: &LogKey) -> Option<(StatusUpdate, Log)> {
+        if let Some(idx) = self
+            .unconfirmed
+            .iter()
+            .position(|(_, l)| log_key(l) == *key)
+        {
+            return Some(self.unconfirmed.remove(idx));
+        }
+        if let Some(idx) = self.ready.iter().position(|(_, l)| log_key(l) == *key) {
+            return self.ready.remove(idx);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{FixedBytes, U256};
+
+    fn make_event(task_id: u64, block_number: u64) -> (StatusUpdate, Log) {
+        let event = StatusUpdate {
+            taskId: U256::from(task_id),
+            protocol: FixedBytes::ZERO,
+            statusBefore: 0,
+            statusAfter: 1,
+        };
+        let log = Log {
+            block_number: Some(block_number),
+            block_hash: Some(B256::with_last_byte(block_number as u8)),
+            log_index: Some(0),
+            ..Default::default()
+        };
+
+        (event, log)
+    }
+
+    #[test]
+    fn test_task_queue_confirmations() {
+        let mut queue = TaskQueue::new(2);
+        let (event, log) = make_event(1, 10);
+        queue.push(event, log);
+        let (event, log) = make_event(2, 11);
+        let key = log_key(&log);
+        queue.push(event, log);
+        assert_eq!(queue.len(), 0);
+        assert_eq!(queue.unconfirmed_blocks(), BTreeSet::from([10, 11]));
+
+        // first event has 2 confirmations at block 12
+        let confirmed = queue.take_confirmed(12);
+        assert_eq!(confirmed.len(), 1);
+        assert_eq!(confirmed[0].0.taskId, U256::from(1));
+        assert!(queue.has_unconfirmed());
+
+        // second event is removed due to a reorg
+        assert!(queue.remove(&key).is_some());
+        assert!(!queue.has_unconfirmed());
+    }
+
+    #[test]
+    fn test_task_queue_duplicates() {
+        let mut queue = TaskQueue::new(0);
+        let (event, log) = make_event(1, 10);
+        assert!(queue.push(event.clone(), log.clone()));
+        assert!(!queue.push(event.clone(), log.clone()));
+        assert_eq!(queue.len(), 1);
+
+        // can be pushed again once forgotten
+        queue.forget(&event);
+        assert!(queue.push(event, log));
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn test_task_queue_no_confirmations() {
+        let mut queue = TaskQueue::new(0);
+        let (event, log) = make_event(1, 10);
+        queue.push(event, log);
+        assert_eq!(queue.len(), 1);
+        assert!(!queue.has_unconfirmed());
+        assert!(queue.pop().is_some());
+    }
+
+    #[test]
+    fn test_task_queue_pop_where() {
+        let mut queue = TaskQueue::new(0);
+        for task_id in 1..=3 {
+            let (event, log) = make_event(task_id, task_id);
+            queue.push(event, log);
+        }
+
+        let (event, _) = queue
+            .pop_where(|event| event.taskId == U256::from(2))
+            .unwrap();
+        assert_eq!(event.taskId, U256::from(2));
+        assert!(queue
+            .pop_where(|event| event.taskId > U256::from(3))
+            .is_none());
+        assert_eq!(
+            queue.iter().map(|(e, _)| e.taskId).collect::<Vec<_>>(),
+            vec![U256::from(1), U256::from(3)]
+        );
+    }
+}
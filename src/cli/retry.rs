@@ -0,0 +1,287 @@
+use super::queue::{log_key, LogKey};
+use crate::{node::is_simulation_revert, OracleCoordinator::StatusUpdate};
+use alloy::{primitives::U256, rpc::types::Log};
+use std::{collections::HashMap, time::Duration};
+use tokio::time::Instant;
+
+/// Upper bound for the delay between two attempts of a task.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// A queue of failed tasks that are waiting to be retried, with exponential backoff.
+///
+/// The number of attempts are tracked per log, and a task is given up on
+/// once it has failed `max_retries + 1` times in total.
+pub struct RetryQueue {
+    /// Maximum number of retries for a task, excluding its first attempt.
+    max_retries: u32,
+    /// Delay before the first retry, doubled for each subsequent retry.
+    base_delay: Duration,
+    /// Number of failed attempts so far, per log.
+    attempts: HashMap<LogKey, u32>,
+    /// Tasks that are waiting for their retry time.
+    scheduled: Vec<(Instant, StatusUpdate, Log)>,
+}
+
+impl RetryQueue {
+    /// Creates a new retry queue.
+    pub fn new(max_retries: u32, base_delay: Duration) -> Self {
+        Self {
+            max_retries,
+            base_delay,
+            attempts: HashMap::new(),
+            scheduled: Vec::new(),
+        }
+    }
+
+    /// Records a failed attempt for the task, and schedules it for a retry.
+    ///
+    /// Returns the delay until the retry, or `None` if there are no retries left;
+    /// in which case the task is forgotten.
+    pub fn schedule(&mut self, event: StatusUpdate, log: Log) -> Option<Duration> {
+        let key = log_key(&log);
+        let attempts = self.attempts.entry(key).or_default();
+        *attempts += 1;
+        let attempts = *attempts;
+        if attempts > self.max_retries {
+            self.attempts.remove(&key);
+            return None;
+        }
+
+        let delay = self.delay(attempts);
+        self.scheduled.push((Instant::now() + delay, event, log));
+        Some(delay)
+    }
+
+    /// Schedules a task to be tried again after the given delay, without counting it as a failed attempt;
+    /// e.g. when the fees are too high at the moment.
+    pub fn defer(&mut self, event: StatusUpdate, log: Log, delay: Duration) {
+        self.scheduled.push((Instant::now() + delay, event, log));
+    }
+
+    /// Returns the delay before the given retry, starting from 1.
+    fn delay(&self, retry: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(MAX_RETRY_DELAY)
+    }
+
+    /// Number of failed attempts of the task with the given log key.
+    pub fn attempts(&self, key: &LogKey) -> u32 {
+        self.attempts.get(key).copied().unwrap_or_default()
+    }
+
+    /// Forgets the attempts of a task, e.g. when it is processed successfully.
+    pub fn clear(&mut self, key: &LogKey) {
+        self.attempts.remove(key);
+    }
+
+    /// Returns the earliest time at which a scheduled task is due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.scheduled.iter().map(|(due, _, _)| *due).min()
+    }
+
+    /// Returns the log keys & task ids of the scheduled tasks that are due w.r.t the given time,
+    /// without taking them; see [`RetryQueue::take`].
+    pub fn due(&self, now: Instant) -> Vec<(LogKey, U256)> {
+        self.scheduled
+            .iter()
+            .filter(|(due, _, _)| *due <= now)
+            .map(|(_, event, log)| (log_key(log), event.taskId))
+            .collect()
+    }
+
+    /// Takes the scheduled task with the given log key, keeping its attempts.
+    pub fn take(&mut self, key: &LogKey) -> Option<(StatusUpdate, Log)> {
+        let idx = self
+            .scheduled
+            .iter()
+            .position(|(_, _, l)| log_key(l) == *key)?;
+        let (_, event, log) = self.scheduled.remove(idx);
+
+        Some((event, log))
+    }
+
+    /// Removes the scheduled task with the given log key, along with its attempts.
+    pub fn remove(&mut self, key: &LogKey) -> Option<(StatusUpdate, Log)> {
+        self.attempts.remove(key);
+        let idx = self
+            .scheduled
+            .iter()
+            .position(|(_, _, l)| log_key(l) == *key)?;
+        let (_, event, log) = self.scheduled.remove(idx);
+
+        Some((event, log))
+    }
+
+    /// Iterates over the tasks waiting to be retried, along with their retry times.
+    pub fn iter(&self) -> impl Iterator<Item = &(Instant, StatusUpdate, Log)> {
+        self.scheduled.iter()
+    }
+
+    /// Number of tasks waiting to be retried.
+    pub fn len(&self) -> usize {
+        self.scheduled.len()
+    }
+
+    /// Returns `true` if there are no tasks waiting to be retried.
+    pub fn is_empty(&self) -> bool {
+        self.scheduled.is_empty()
+    }
+}
+
+/// Returns `true` if a task that failed with the given error is worth retrying,
+/// e.g. due to a transient RPC failure, a rate limit or a download timeout.
+///
+/// Errors that will not go away by retrying, such as a contract revert due to the
+/// task status or an invalid input, are considered permanent. Unknown errors are
+/// also considered permanent, so that the node does not spend resources on them.
+///
+/// Transactions that revert in simulation are permanent as well. Otherwise, there is
+/// no common error type among the providers, so we check the messages within the error chain.
+pub fn is_retryable_error(error: &eyre::Report) -> bool {
+    const PERMANENT_PATTERNS: [&str; 9] = [
+        "already responded",
+        "already validated",
+        "invalid status for task",
+        "invalid nonce for task",
+        "not registered",
+        "not whitelisted",
+        "insufficient",
+        "execution reverted",
+        "could not parse",
+    ];
+    const RETRYABLE_PATTERNS: [&str; 13] = [
+        "timed out",
+        "timeout",
+        "429",
+        "too many requests",
+        "rate limit",
+        "connection",
+        "transport error",
+        "could not get task",
+        "could not simulate transaction",
+        "from arweave",
+        "bad gateway",
+        "service unavailable",
+        "temporarily unavailable",
+    ];
+
+    if is_simulation_revert(error) {
+        return false;
+    }
+
+    let messages = error
+        .chain()
+        .map(|e| e.to_string().to_lowercase())
+        .collect::<Vec<_>>();
+    let matches = |patterns: &[&str]| {
+        messages
+            .iter()
+            .any(|message| patterns.iter().any(|pattern| message.contains(pattern)))
+    };
+
+    !matches(&PERMANENT_PATTERNS) && matches(&RETRYABLE_PATTERNS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimulationReverted;
+    use alloy::primitives::{FixedBytes, B256, U256};
+    use eyre::{eyre, WrapErr};
+
+    fn make_event(task_id: u64) -> (StatusUpdate, Log) {
+        let event = StatusUpdate {
+            taskId: U256::from(task_id),
+            protocol: FixedBytes::ZERO,
+            statusBefore: 0,
+            statusAfter: 1,
+        };
+        let log = Log {
+            block_number: Some(task_id),
+            block_hash: Some(B256::with_last_byte(task_id as u8)),
+            log_index: Some(0),
+            ..Default::default()
+        };
+
+        (event, log)
+    }
+
+    #[test]
+    fn test_retry_queue() {
+        let mut retries = RetryQueue::new(2, Duration::from_secs(1));
+        let (event, log) = make_event(1);
+        let key = log_key(&log);
+
+        // first retry after 1s, second after 2s
+        assert_eq!(
+            retries.schedule(event.clone(), log.clone()),
+            Some(Duration::from_secs(1))
+        );
+        let now = Instant::now();
+        assert!(retries.due(now).is_empty());
+        let due = retries.due(now + Duration::from_secs(1));
+        assert_eq!(due, vec![(key, event.taskId)]);
+        assert!(retries.take(&key).is_some());
+        assert!(retries.is_empty());
+
+        assert_eq!(
+            retries.schedule(event.clone(), log.clone()),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(retries.attempts(&key), 2);
+        assert!(retries.due(now).is_empty());
+        assert_eq!(
+            retries.due(now + Duration::from_secs(3)),
+            vec![(key, event.taskId)]
+        );
+        assert!(retries.take(&key).is_some());
+        assert_eq!(retries.attempts(&key), 2);
+        retries.defer(event.clone(), log.clone(), Duration::ZERO);
+        assert!(retries.remove(&key).is_some());
+        assert_eq!(retries.attempts(&key), 0);
+
+        // no retries left after the attempts are exhausted
+        retries.schedule(event.clone(), log.clone());
+        retries.schedule(event.clone(), log.clone());
+        assert_eq!(retries.schedule(event.clone(), log.clone()), None);
+        assert_eq!(retries.attempts(&key), 0);
+
+        // deferred tasks are not counted as attempts
+        retries.defer(event, log, Duration::from_secs(30));
+        assert_eq!(retries.attempts(&key), 0);
+        assert_eq!(retries.len(), 1);
+    }
+
+    #[test]
+    fn test_is_retryable_error() {
+        let err = Err::<(), _>(eyre!("error sending request: connection refused"))
+            .wrap_err("could not get task")
+            .unwrap_err();
+        assert!(is_retryable_error(&err));
+        assert!(is_retryable_error(&eyre!(
+            "server returned an error response: error code 429: Too Many Requests"
+        )));
+        assert!(is_retryable_error(&eyre!("Generation workflow timed out")));
+
+        assert!(!is_retryable_error(&eyre!(
+            "Invalid status for task: 1 (have: 2, want: 1)"
+        )));
+        assert!(!is_retryable_error(&eyre!("Already validated 1")));
+        assert!(!is_retryable_error(&eyre!("something unexpected")));
+
+        // reverts in simulation are permanent, while the other simulation errors are not
+        let err = eyre::Report::new(SimulationReverted {
+            method: "respond".to_string(),
+            reason: "Unhandled contract error: execution reverted".to_string(),
+        })
+        .wrap_err("could not respond");
+        assert!(!is_retryable_error(&err));
+        let err = Err::<(), _>(eyre!(
+            "server returned an error response: error code -32005: limit exceeded"
+        ))
+        .wrap_err("could not simulate transaction")
+        .unwrap_err();
+        assert!(is_retryable_error(&err));
+    }
+}
@@ -0,0 +1,81 @@
+use super::health::HealthState;
+use crate::metrics;
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use eyre::{Context, Result};
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+/// Serves the HTTP endpoints of the node at the given address, until cancelled.
+///
+/// - `/metrics` returns the Prometheus metrics, only if `metrics` is set.
+/// - `/healthz` returns `200` as long as the process is up.
+/// - `/readyz` returns `200` if the node is ready w.r.t the given health state, `503` otherwise,
+///   along with the status of each check.
+pub async fn serve(
+    addr: SocketAddr,
+    health: HealthState,
+    metrics: bool,
+    cancellation: CancellationToken,
+) -> Result<()> {
+    let mut app = Router::new()
+        .route("/healthz", get(get_health))
+        .route("/readyz", get(get_readiness));
+    if metrics {
+        app = app.route("/metrics", get(get_metrics));
+    }
+    let app = app.with_state(health);
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .wrap_err_with(|| format!("could not bind to {}", addr))?;
+    if metrics {
+        log::info!("Serving metrics & health checks at http://{}", addr);
+    } else {
+        log::info!("Serving health checks at http://{}", addr);
+    }
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { cancellation.cancelled().await })
+        .await
+        .wrap_err("could not serve HTTP endpoints")
+}
+
+async fn get_metrics() -> impl IntoResponse {
+    match metrics::gather() {
+        Ok(body) => (
+            StatusCode::OK,
+            [(CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+            body,
+        ),
+        Err(e) => {
+            log::error!("Could not gather metrics: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                [(CONTENT_TYPE, "text/plain")],
+                e.to_string(),
+            )
+        }
+    }
+}
+
+async fn get_health() -> impl IntoResponse {
+    (StatusCode::OK, "ok")
+}
+
+async fn get_readiness(State(health): State<HealthState>) -> impl IntoResponse {
+    let (ready, status) = health.readiness();
+    let code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (code, Json(status))
+}
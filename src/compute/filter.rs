@@ -0,0 +1,237 @@
+use crate::contracts::bytes32_to_string;
+use alloy::primitives::FixedBytes;
+use eyre::{eyre, Context, Result};
+use semver::{Version, VersionReq};
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// A pattern to match task protocols against, in the form `name[/version]`.
+///
+/// - The name may contain `*` wildcards, e.g. `swan-*` or `*`.
+/// - The version is an optional semver requirement such as `>=0.1.0, <0.2.0` or `*`,
+///   and a missing version matches all versions.
+#[derive(Debug, Clone)]
+pub struct ProtocolPattern {
+    name: String,
+    version: Option<VersionReq>,
+}
+
+impl ProtocolPattern {
+    /// Returns `true` if the given protocol name & version match this pattern.
+    ///
+    /// A protocol without a (valid) version does not match a pattern that requires a version.
+    pub fn matches(&self, name: &str, version: Option<&Version>) -> bool {
+        if !wildcard_match(&self.name, name) {
+            return false;
+        }
+
+        match (&self.version, version) {
+            (None, _) => true,
+            (Some(req), _) if *req == VersionReq::STAR => true,
+            (Some(req), Some(version)) => req.matches(version),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+impl FromStr for ProtocolPattern {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (name, version) = match value.split_once('/') {
+            Some((name, version)) => (name.trim(), Some(version.trim())),
+            None => (value.trim(), None),
+        };
+        if name.is_empty() {
+            return Err(eyre!("Protocol name must not be empty: {}", value));
+        }
+
+        let version = version
+            .map(|version| {
+                VersionReq::parse(version)
+                    .wrap_err_with(|| format!("invalid version requirement: {}", version))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            version,
+        })
+    }
+}
+
+impl std::fmt::Display for ProtocolPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}/{}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Decides which task protocols are served by the node.
+///
+/// A protocol is served if it matches at least one of the included patterns (or if there are none),
+/// and none of the excluded patterns. The default filter serves all protocols.
+///
+/// The number of ignored tasks is shared among the clones of a filter.
+#[derive(Debug, Clone, Default)]
+pub struct ProtocolFilter {
+    include: Vec<ProtocolPattern>,
+    exclude: Vec<ProtocolPattern>,
+    ignored: Arc<AtomicU64>,
+}
+
+impl ProtocolFilter {
+    /// Creates a new filter with the given included & excluded patterns.
+    pub fn new(include: Vec<ProtocolPattern>, exclude: Vec<ProtocolPattern>) -> Self {
+        Self {
+            include,
+            exclude,
+            ignored: Default::default(),
+        }
+    }
+
+    /// Returns `true` if the filter has no patterns, i.e. it serves all protocols.
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Returns `true` if the given protocol string, e.g. `swan-buyer-purchase/0.1.0`, is served.
+    pub fn allows(&self, protocol: &str) -> bool {
+        let (name, version) = match protocol.split_once('/') {
+            Some((name, version)) => (name, parse_version(version)),
+            None => (protocol, None),
+        };
+        let matches = |pattern: &ProtocolPattern| pattern.matches(name, version.as_ref());
+
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+
+    /// Returns `true` if the given `bytes32` protocol is served.
+    ///
+    /// Protocols that can not be decoded are only served if the filter is empty.
+    pub fn allows_bytes32(&self, protocol: &FixedBytes<32>) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+
+        bytes32_to_string(protocol)
+            .map(|protocol| self.allows(&protocol))
+            .unwrap_or(false)
+    }
+
+    /// Counts an ignored task, and returns the total number of ignored tasks so far.
+    pub fn record_ignored(&self) -> u64 {
+        self.ignored.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Returns the number of tasks ignored so far.
+    pub fn ignored(&self) -> u64 {
+        self.ignored.load(Ordering::Relaxed)
+    }
+}
+
+impl std::fmt::Display for ProtocolFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |patterns: &[ProtocolPattern]| {
+            patterns
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        write!(
+            f,
+            "include: [{}], exclude: [{}]",
+            join(&self.include),
+            join(&self.exclude)
+        )
+    }
+}
+
+/// Parses a version leniently, so that versions like `1` or `1.0` are read as `1.0.0`.
+fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim().trim_start_matches('v');
+    Version::parse(version).ok().or_else(|| {
+        let parts = version.split('.').count();
+        match parts {
+            1 => Version::parse(&format!("{}.0.0", version)).ok(),
+            2 => Version::parse(&format!("{}.0", version)).ok(),
+            _ => None,
+        }
+    })
+}
+
+/// Matches the value against a pattern where `*` matches any sequence of characters.
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+
+    // the first part must be a prefix, unless the pattern starts with a wildcard
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        // there are no wildcards, so the value must be equal to the pattern
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("swan-buyer-purchase", "swan-buyer-purchase"));
+        assert!(!wildcard_match("swan-buyer-purchase", "swan-buyer"));
+        assert!(wildcard_match("swan-*", "swan-buyer-purchase"));
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("*-purchase", "swan-buyer-purchase"));
+        assert!(wildcard_match("swan*purchase", "swan-buyer-purchase"));
+        assert!(!wildcard_match("swan*purchase", "swan-buyer-sale"));
+    }
+
+    #[test]
+    fn test_protocol_filter() {
+        let include = vec!["swan-buyer-purchase/*".parse().unwrap()];
+        let filter = ProtocolFilter::new(include, vec![]);
+        assert!(filter.allows("swan-buyer-purchase/0.1.0"));
+        assert!(!filter.allows("dria-oracle/0.1.10"));
+
+        let exclude = vec!["swan-buyer-purchase".parse().unwrap()];
+        let filter = ProtocolFilter::new(vec![], exclude);
+        assert!(!filter.allows("swan-buyer-purchase/0.1.0"));
+        assert!(filter.allows("dria-oracle/0.1.10"));
+
+        let include = vec!["swan-*/>=0.1.0, <0.2.0".parse().unwrap()];
+        let filter = ProtocolFilter::new(include, vec![]);
+        assert!(filter.allows("swan-buyer-purchase/0.1.5"));
+        assert!(filter.allows("swan-buyer-purchase/0.1"));
+        assert!(!filter.allows("swan-buyer-purchase/0.2.0"));
+        assert!(!filter.allows("swan-buyer-purchase"));
+
+        assert!(ProtocolFilter::default().allows("anything/at-all"));
+        assert!("/0.1.0".parse::<ProtocolPattern>().is_err());
+        assert!("swan/not-a-version".parse::<ProtocolPattern>().is_err());
+    }
+}
@@ -1,16 +1,18 @@
 use crate::{
     compute::generation::execute::execute_generation,
-    contracts::{bytes32_to_string, bytes_to_string},
-    mine_nonce,
+    compute::mine_nonce_blocking,
+    contracts::{bytes32_to_string, bytes_to_string, TaskStatus},
+    metrics::{self, TaskLabels},
     storage::ArweaveStorage,
-    DriaOracle,
+    telemetry, DriaOracle, JournalEntry,
 };
 use alloy::{
-    primitives::{FixedBytes, U256},
+    primitives::{keccak256, FixedBytes, U256},
     rpc::types::TransactionReceipt,
 };
 use dkn_workflows::DriaWorkflowsConfig;
 use eyre::{Context, Result};
+use std::cell::Cell;
 
 use super::postprocess::*;
 use super::request::GenerationRequest;
@@ -21,11 +23,20 @@ use super::request::GenerationRequest;
 ///    Contract will revert even if we dont do this check ourselves, but its better to provide the error here.
 ///
 /// 2. Then, we check if our models are compatible with the request. If not, we return an error.
+///
+/// 3. Finally, we check the task status & responses again right before responding,
+///    as the task may have been filled by other generators while we were working on it.
+///
+/// The details of the response are written to the given journal entry along the way,
+/// and `submitted` is set right before the response is sent.
 pub async fn handle_generation(
     node: &DriaOracle,
     workflows: &DriaWorkflowsConfig,
     task_id: U256,
     protocol: FixedBytes<32>,
+    labels: &mut TaskLabels,
+    entry: &mut JournalEntry,
+    submitted: &Cell<bool>,
 ) -> Result<Option<TransactionReceipt>> {
     log::info!("Handling generation task {}", task_id);
 
@@ -43,6 +54,7 @@ pub async fn handle_generation(
         .get_task_request(task_id)
         .await
         .wrap_err("could not get task")?;
+    entry.input_hash = Some(keccak256(&request.input));
 
     // choose model based on the request
     log::debug!("Choosing model to use");
@@ -50,6 +62,9 @@ pub async fn handle_generation(
     let models_vec = models_string.split(',').map(|s| s.to_string()).collect();
     let (_, model) = workflows.get_any_matching_model(models_vec)?;
     log::debug!("Using model: {} from {}", model, models_string);
+    labels.set_model(&model);
+    telemetry::record_model(&model);
+    entry.model = Some(model.to_string());
 
     // parse protocol string early, in case it cannot be parsed
     let protocol_string = bytes32_to_string(&protocol)?;
@@ -57,8 +72,13 @@ pub async fn handle_generation(
     // execute task
     log::debug!("Executing the workflow");
     let input = GenerationRequest::try_parse_bytes(&request.input).await?;
+    let timer = metrics::GENERATION_DURATION
+        .with_label_values(&[&node.chain_id.to_string(), &model.to_string()])
+        .start_timer();
     let output = execute_generation(&input, model, Some(node)).await?;
+    timer.observe_duration();
     log::debug!("Output: {}", output);
+    entry.raw_output = Some(output.clone());
 
     // post-processing
     log::debug!(
@@ -74,7 +94,7 @@ pub async fn handle_generation(
         }?;
 
     // uploading to storage
-    let arweave = ArweaveStorage::new_from_env()?;
+    let arweave = ArweaveStorage::new_from_env()?.with_dry_run(node.config.dry_run);
     let output = if use_storage {
         log::debug!("Uploading output to storage");
         arweave.put_if_large(output).await?
@@ -84,20 +104,69 @@ pub async fn handle_generation(
     };
     log::debug!("Uploading metadata to storage");
     let metadata = arweave.put_if_large(metadata).await?;
+    entry.set_output(&output);
+    entry.set_metadata(&metadata);
 
     // mine nonce
     log::debug!("Mining nonce for task");
-    let nonce = mine_nonce(
+    let timer = metrics::NONCE_MINING_DURATION
+        .with_label_values(&[&node.chain_id.to_string(), "generator"])
+        .start_timer();
+    let nonce = mine_nonce_blocking(
         request.parameters.difficulty,
-        &request.requester,
-        &node.address(),
-        &request.input,
-        &task_id,
+        request.requester,
+        node.address(),
+        request.input.clone(),
+        task_id,
     )
+    .await?
     .nonce;
+    timer.observe_duration();
+    entry.nonce = Some(nonce);
+
+    // check the task again right before responding, as other generators may have
+    // filled the task in the meantime and the transaction would revert otherwise
+    log::debug!("Checking the task status before responding");
+    let request = node
+        .get_task_request(task_id)
+        .await
+        .wrap_err("could not get task")?;
+    if request.status != TaskStatus::PendingGeneration as u8 {
+        log::info!(
+            "Task {} has moved to {}, not responding.",
+            task_id,
+            TaskStatus::try_from(request.status).unwrap_or_default()
+        );
+        return Ok(None);
+    }
+    let responses = node.get_task_responses(task_id).await?;
+    if responses.len() as u64 >= request.parameters.numGenerations.to::<u64>() {
+        log::info!(
+            "Task {} has all of its generations, not responding.",
+            task_id
+        );
+        return Ok(None);
+    }
+    if responses.iter().any(|r| r.responder == node.address()) {
+        log::debug!("Already responded to {} with generation", task_id);
+        return Ok(None);
+    }
+
+    if node.config.dry_run {
+        log::info!(
+            "Dry run, not responding to task {} with nonce {}, output: {}, metadata: {}",
+            task_id,
+            nonce,
+            entry.output.as_deref().unwrap_or_default(),
+            entry.metadata.as_deref().unwrap_or_default()
+        );
+        entry.set_dry_run();
+        return Ok(None);
+    }
 
     // respond
     log::debug!("Responding with generation");
+    submitted.set(true);
     let tx_receipt = node
         .respond_generation(task_id, output, metadata, nonce)
         .await?;
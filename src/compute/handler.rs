@@ -1,51 +1,81 @@
 use crate::{
-    contracts::{OracleCoordinator::StatusUpdate, OracleKind, TaskStatus},
-    DriaOracle,
+    contracts::{bytes32_to_string, OracleCoordinator::StatusUpdate, OracleKind, TaskStatus},
+    metrics::TaskLabels,
+    DriaOracle, Journal, JournalEntry,
 };
 use alloy::rpc::types::TransactionReceipt;
 use dkn_workflows::DriaWorkflowsConfig;
 use eyre::Result;
+use std::cell::Cell;
 
-use super::{handle_generation, handle_validation};
+use super::{handle_generation, handle_validation, ProfitabilityGate, ProtocolFilter};
 
 /// Handles a task request.
 ///
+/// - Tasks with protocols that are not allowed by the filter are ignored
+/// - Tasks that are not profitable w.r.t the optional gate are ignored
 /// - Generation tasks are forwarded to `handle_generation`
 /// - Validation tasks are forwarded to `handle_validation`
+///
+/// Handled tasks are recorded to the optional journal along with their outcome,
+/// and their rewards are recorded once they are completed.
+///
+/// The optional `submitted` flag is set right before the response is sent, so that the caller
+/// knows when the task should no longer be cancelled.
 pub async fn handle_request(
     node: &DriaOracle,
     kinds: &[OracleKind],
     workflows: &DriaWorkflowsConfig,
+    protocols: &ProtocolFilter,
+    profitability: Option<&ProfitabilityGate>,
+    journal: Option<&Journal>,
+    submitted: Option<&Cell<bool>>,
     event: StatusUpdate,
 ) -> Result<Option<TransactionReceipt>> {
+    let not_tracked = Cell::default();
+    let submitted = submitted.unwrap_or(&not_tracked);
+
     log::debug!("Received event for task {} ()", event.taskId);
 
     // we check the `statusAfter` field of the event, which indicates the final status of the listened task
-    let response_tx_hash = match TaskStatus::try_from(event.statusAfter)? {
+    let status = TaskStatus::try_from(event.statusAfter)?;
+    let mut labels = TaskLabels::new(node.chain_id, status, &event.protocol);
+    labels.seen();
+
+    let kind = match status {
         TaskStatus::PendingGeneration => {
-            if kinds.contains(&OracleKind::Generator) {
-                handle_generation(node, workflows, event.taskId, event.protocol).await?
-            } else {
+            if !kinds.contains(&OracleKind::Generator) {
                 log::debug!(
                     "Ignoring generation task {} as you are not generator.",
                     event.taskId
                 );
+                labels.ignored();
                 return Ok(None);
             }
+            OracleKind::Generator
         }
         TaskStatus::PendingValidation => {
-            if kinds.contains(&OracleKind::Validator) {
-                handle_validation(node, event.taskId).await?
-            } else {
+            if !kinds.contains(&OracleKind::Validator) {
                 log::debug!(
                     "Ignoring generation task {} as you are not validator.",
                     event.taskId
                 );
+                labels.ignored();
                 return Ok(None);
             }
+            OracleKind::Validator
         }
         TaskStatus::Completed => {
             log::debug!("Task {} is completed.", event.taskId);
+            if let Some(journal) = journal {
+                if let Err(e) = journal.observe_completion(node, event.taskId).await {
+                    log::error!(
+                        "Could not record the reward of task {}: {:?}",
+                        event.taskId,
+                        e
+                    );
+                }
+            }
             return Ok(None);
         }
         // this is kind of unexpected, but we dont have to return an error just for this
@@ -55,5 +85,60 @@ pub async fn handle_request(
         }
     };
 
-    Ok(response_tx_hash)
+    // ignore the tasks of protocols that we dont serve
+    if !protocols.allows_bytes32(&event.protocol) {
+        log::info!(
+            "Ignoring task {} with protocol {} ({} ignored so far).",
+            event.taskId,
+            bytes32_to_string(&event.protocol).unwrap_or_else(|_| event.protocol.to_string()),
+            protocols.record_ignored()
+        );
+        labels.ignored();
+        return Ok(None);
+    }
+
+    let protocol = bytes32_to_string(&event.protocol).unwrap_or_default();
+    let mut entry = JournalEntry::new(event.taskId, kind, protocol, node.address());
+    let result = async {
+        // defer the tasks while the fees are above the ceiling, before doing any work for them
+        if !node.config.dry_run {
+            node.check_fee_ceiling().await?;
+        }
+
+        // ignore the tasks that are not worth it
+        if let Some(gate) = profitability {
+            if !gate.check(node, workflows, kind, event.taskId).await? {
+                return Ok(None);
+            }
+        }
+
+        match kind {
+            OracleKind::Generator => {
+                handle_generation(
+                    node,
+                    workflows,
+                    event.taskId,
+                    event.protocol,
+                    &mut labels,
+                    &mut entry,
+                    submitted,
+                )
+                .await
+            }
+            OracleKind::Validator => {
+                handle_validation(node, event.taskId, &mut labels, &mut entry, submitted).await
+            }
+        }
+    }
+    .await;
+    labels.record(&result);
+
+    if let Some(journal) = journal {
+        entry.finish(&result);
+        if let Err(e) = journal.record(&entry) {
+            log::error!("Could not record task {} to journal: {:?}", event.taskId, e);
+        }
+    }
+
+    result
 }
@@ -1,8 +1,14 @@
 mod handler;
 pub use handler::handle_request;
 
+mod filter;
+pub use filter::{ProtocolFilter, ProtocolPattern};
+
+mod profit;
+pub use profit::{ProfitEstimate, ProfitabilityGate};
+
 mod nonce;
-pub use nonce::mine_nonce;
+pub use nonce::{mine_nonce, mine_nonce_blocking};
 
 mod generation;
 pub use generation::handle_generation;
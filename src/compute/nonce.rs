@@ -47,6 +47,23 @@ pub fn mine_nonce(
     }
 }
 
+/// Mines a nonce on a blocking thread, see [`mine_nonce`].
+///
+/// Mining is CPU-bound, so that it would otherwise block the event loop & the other tasks meanwhile.
+pub async fn mine_nonce_blocking(
+    difficulty: u8,
+    requester: Address,
+    responder: Address,
+    input: Bytes,
+    task_id: U256,
+) -> eyre::Result<NonceResult> {
+    tokio::task::spawn_blocking(move || {
+        mine_nonce(difficulty, &requester, &responder, &input, &task_id)
+    })
+    .await
+    .map_err(|e| eyre::eyre!("could not mine nonce: {}", e))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
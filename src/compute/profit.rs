@@ -0,0 +1,246 @@
+use crate::{
+    contracts::{bytes_to_string, OracleKind},
+    DriaOracle,
+};
+use alloy::primitives::{utils::format_ether, U256};
+use dkn_workflows::{DriaWorkflowsConfig, Model};
+use eyre::{Context, Result};
+
+/// Estimated gas used by a `respond` transaction, which may also update the task status.
+const GENERATION_GAS_ESTIMATE: u64 = 300_000;
+/// Estimated gas used by a `validate` transaction, which may also finalize the task.
+const VALIDATION_GAS_ESTIMATE: u64 = 500_000;
+/// Estimated number of output tokens for a single workflow execution.
+const ESTIMATED_OUTPUT_TOKENS: u64 = 1_000;
+/// Rough number of bytes per token, used to estimate the number of input tokens.
+const BYTES_PER_TOKEN: u64 = 4;
+
+/// Estimated reward & cost of handling a task, in terms of wei.
+///
+/// The fee token is assumed to be priced the same as the native token, e.g. WETH.
+#[derive(Debug, Clone, Copy)]
+pub struct ProfitEstimate {
+    /// Fee of the task for this oracle, if it is paid.
+    pub fee: U256,
+    /// Probability of being paid w.r.t the deviation factor of the coordinator.
+    pub payout_probability: f64,
+    /// Expected reward, i.e. the fee times the payout probability.
+    pub reward: U256,
+    /// Estimated gas cost of the transaction at the gas price of the fee policy.
+    pub gas_cost: U256,
+    /// Estimated cost of the model usage w.r.t the configured token prices.
+    pub model_cost: U256,
+}
+
+impl ProfitEstimate {
+    /// Total estimated cost of the task.
+    pub fn cost(&self) -> U256 {
+        self.gas_cost + self.model_cost
+    }
+
+    /// Returns `true` if the expected reward exceeds the cost by at least the given margin.
+    pub fn is_profitable(&self, min_margin: f64) -> bool {
+        let reward = f64::from(self.reward);
+        let cost = f64::from(self.cost());
+        reward >= cost * (1.0 + min_margin)
+    }
+}
+
+impl std::fmt::Display for ProfitEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "reward: {} (fee {} with {:.1}% payout probability), cost: {} (gas {} + model {})",
+            format_ether(self.reward),
+            format_ether(self.fee),
+            self.payout_probability * 100.0,
+            format_ether(self.cost()),
+            format_ether(self.gas_cost),
+            format_ether(self.model_cost),
+        )
+    }
+}
+
+/// A pre-flight check that skips the tasks that are not worth handling.
+///
+/// The expected reward is the fee of the task (as computed by `getFee` at request time)
+/// weighted by the probability of being within the deviation factor of the coordinator,
+/// and the cost is the gas of the response at the gas price of the fee policy plus the model usage.
+#[derive(Debug, Clone, Default)]
+pub struct ProfitabilityGate {
+    /// Minimum margin of the expected reward over the cost, e.g. `0.1` for 10%.
+    pub min_margin: f64,
+    /// Price per 1M tokens of each model, in terms of ether. Models without a price are free.
+    pub model_prices: Vec<(Model, f64)>,
+}
+
+impl ProfitabilityGate {
+    /// Creates a new gate with the given minimum margin & model prices.
+    pub fn new(min_margin: f64, model_prices: Vec<(Model, f64)>) -> Self {
+        Self {
+            min_margin,
+            model_prices,
+        }
+    }
+
+    /// Returns the price per 1M tokens of the given model.
+    fn model_price(&self, model: &Model) -> f64 {
+        self.model_prices
+            .iter()
+            .find(|(m, _)| m == model)
+            .map(|(_, price)| *price)
+            .unwrap_or_default()
+    }
+
+    /// Estimates the reward & cost of the task for the given kind, and returns `true` if it
+    /// should be handled. Tasks that are not profitable are logged along with the numbers.
+    pub async fn check(
+        &self,
+        node: &DriaOracle,
+        workflows: &DriaWorkflowsConfig,
+        kind: OracleKind,
+        task_id: U256,
+    ) -> Result<bool> {
+        let estimate = self
+            .estimate(node, workflows, kind, task_id)
+            .await
+            .wrap_err("could not estimate profitability")?;
+
+        if estimate.is_profitable(self.min_margin) {
+            log::debug!("Task {} is profitable, {}", task_id, estimate);
+            Ok(true)
+        } else {
+            log::info!(
+                "Skipping task {} as it is below the {:.1}% margin, {}",
+                task_id,
+                self.min_margin * 100.0,
+                estimate
+            );
+            Ok(false)
+        }
+    }
+
+    /// Estimates the reward & cost of the task for the given kind.
+    pub async fn estimate(
+        &self,
+        node: &DriaOracle,
+        workflows: &DriaWorkflowsConfig,
+        kind: OracleKind,
+        task_id: U256,
+    ) -> Result<ProfitEstimate> {
+        let request = node.get_task_request(task_id).await?;
+        let (generation_deviation, validation_deviation) = node.get_deviation_factors().await?;
+        let gas_price = U256::from(node.estimate_gas_price().await?);
+        let num_generations = request.parameters.numGenerations.to::<u64>();
+        let num_validations = request.parameters.numValidations.to::<u64>();
+        let input_tokens = request.input.len() as u64 / BYTES_PER_TOKEN;
+
+        let (fee, payout_probability, gas, model, tokens) = match kind {
+            OracleKind::Generator => {
+                let models_string = bytes_to_string(&request.models)?;
+                let models = models_string.split(',').map(|s| s.to_string()).collect();
+                let (_, model) = workflows.get_any_matching_model(models)?;
+
+                (
+                    request.generatorFee,
+                    payout_probability(generation_deviation, num_generations),
+                    GENERATION_GAS_ESTIMATE,
+                    model,
+                    input_tokens + ESTIMATED_OUTPUT_TOKENS,
+                )
+            }
+            OracleKind::Validator => {
+                // each generation is validated separately, along with the input
+                let responses = node.get_task_responses(task_id).await?;
+                let response_tokens = responses
+                    .iter()
+                    .map(|r| r.output.len() as u64 / BYTES_PER_TOKEN)
+                    .sum::<u64>();
+
+                (
+                    request.validatorFee * U256::from(num_generations),
+                    payout_probability(validation_deviation, num_validations),
+                    VALIDATION_GAS_ESTIMATE,
+                    Model::GPT4o, // all validations use Gpt 4o
+                    num_generations * (input_tokens + ESTIMATED_OUTPUT_TOKENS) + response_tokens,
+                )
+            }
+        };
+
+        let model_cost_eth = self.model_price(&model) * tokens as f64 / 1_000_000.0;
+        Ok(ProfitEstimate {
+            fee,
+            payout_probability,
+            reward: scale(fee, payout_probability),
+            gas_cost: gas_price * U256::from(gas),
+            model_cost: U256::from((model_cost_eth * 1e18) as u128),
+        })
+    }
+}
+
+/// Probability of a score being within `deviation_factor` standard deviations of the mean,
+/// assuming that the scores are normally distributed.
+///
+/// Everyone is paid when there is a single participant, as the deviation is zero.
+fn payout_probability(deviation_factor: u64, participants: u64) -> f64 {
+    if participants <= 1 {
+        return 1.0;
+    }
+
+    erf(deviation_factor as f64 / std::f64::consts::SQRT_2)
+}
+
+/// Approximation of the error function, with a maximum error of `1.5e-7`.
+///
+/// See Abramowitz & Stegun, formula 7.1.26.
+fn erf(x: f64) -> f64 {
+    const A: [f64; 5] = [
+        0.254829592,
+        -0.284496736,
+        1.421413741,
+        -1.453152027,
+        1.061405429,
+    ];
+    const P: f64 = 0.3275911;
+
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + P * x);
+    let poly = A.iter().rev().fold(0.0, |acc, a| acc * t + a) * t;
+
+    sign * (1.0 - poly * (-x * x).exp())
+}
+
+/// Multiplies a wei amount with a ratio.
+fn scale(amount: U256, ratio: f64) -> U256 {
+    const PRECISION: u64 = 1_000_000;
+    amount * U256::from((ratio.clamp(0.0, 1.0) * PRECISION as f64) as u64) / U256::from(PRECISION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payout_probability() {
+        assert_eq!(payout_probability(0, 1), 1.0);
+        assert!((payout_probability(1, 3) - 0.6827).abs() < 1e-3);
+        assert!((payout_probability(2, 3) - 0.9545).abs() < 1e-3);
+        assert!((payout_probability(3, 3) - 0.9973).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_profit_estimate() {
+        let estimate = ProfitEstimate {
+            fee: U256::from(1000),
+            payout_probability: 0.5,
+            reward: scale(U256::from(1000), 0.5),
+            gas_cost: U256::from(300),
+            model_cost: U256::from(100),
+        };
+        assert_eq!(estimate.reward, U256::from(500));
+        assert_eq!(estimate.cost(), U256::from(400));
+        assert!(estimate.is_profitable(0.2));
+        assert!(!estimate.is_profitable(0.3));
+    }
+}
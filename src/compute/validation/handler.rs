@@ -1,14 +1,33 @@
-use crate::{mine_nonce, storage::ArweaveStorage, DriaOracle};
-use alloy::{primitives::U256, rpc::types::TransactionReceipt};
+use crate::{
+    compute::mine_nonce_blocking,
+    contracts::TaskStatus,
+    metrics::{self, TaskLabels},
+    storage::ArweaveStorage,
+    telemetry, DriaOracle, JournalEntry,
+};
+use alloy::{
+    primitives::{keccak256, U256},
+    rpc::types::TransactionReceipt,
+};
 use dkn_workflows::Model;
 use eyre::{eyre, Context, Result};
+use std::cell::Cell;
 
 use super::execute::execute_validations;
 
 /// Handles a validation request.
+///
+/// The task status & validations are checked again right before responding,
+/// as the task may have been filled by other validators while we were working on it.
+///
+/// The details of the response are written to the given journal entry along the way,
+/// and `submitted` is set right before the response is sent.
 pub async fn handle_validation(
     node: &DriaOracle,
     task_id: U256,
+    labels: &mut TaskLabels,
+    entry: &mut JournalEntry,
+    submitted: &Cell<bool>,
 ) -> Result<Option<TransactionReceipt>> {
     log::info!("Handling validation task {}", task_id);
 
@@ -36,6 +55,7 @@ pub async fn handle_validation(
         .get_task_request(task_id)
         .await
         .wrap_err("could not get task request")?;
+    entry.input_hash = Some(keccak256(&request.input));
 
     // fetch each generation response & download its metadata
     log::debug!("Fetching response messages");
@@ -53,7 +73,14 @@ pub async fn handle_validation(
     // validate each response
     log::debug!("Computing validation scores");
     let model = Model::GPT4o; // all validations use Gpt 4o
+    labels.set_model(&model);
+    telemetry::record_model(&model);
+    entry.model = Some(model.to_string());
+    let timer = metrics::VALIDATION_DURATION
+        .with_label_values(&[&node.chain_id.to_string(), &model.to_string()])
+        .start_timer();
     let validations = execute_validations(input, generations, model).await?;
+    timer.observe_duration();
     let scores = validations
         .iter()
         .map(|v| v.final_score_as_solidity_type())
@@ -61,25 +88,80 @@ pub async fn handle_validation(
     let metadata =
         serde_json::to_string(&validations).wrap_err("could not serialize validations")?;
     log::debug!("Validation metadata:\n{}", metadata);
+    entry.raw_output = Some(metadata.clone());
+    entry.output = Some(
+        scores
+            .iter()
+            .map(|score| score.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+    );
 
     // uploading to storage
     log::debug!("Uploading metadata to storage");
-    let arweave = ArweaveStorage::new_from_env()?;
+    let arweave = ArweaveStorage::new_from_env()?.with_dry_run(node.config.dry_run);
     let metadata = arweave.put_if_large(metadata.into()).await?;
+    entry.set_metadata(&metadata);
 
     // mine nonce
     log::debug!("Mining nonce for task");
-    let nonce = mine_nonce(
+    let timer = metrics::NONCE_MINING_DURATION
+        .with_label_values(&[&node.chain_id.to_string(), "validator"])
+        .start_timer();
+    let nonce = mine_nonce_blocking(
         request.parameters.difficulty,
-        &request.requester,
-        &node.address(),
-        &request.input,
-        &task_id,
+        request.requester,
+        node.address(),
+        request.input.clone(),
+        task_id,
     )
+    .await?
     .nonce;
+    timer.observe_duration();
+    entry.nonce = Some(nonce);
+
+    // check the task again right before responding, as other validators may have
+    // filled the task in the meantime and the transaction would revert otherwise
+    log::debug!("Checking the task status before responding");
+    let request = node
+        .get_task_request(task_id)
+        .await
+        .wrap_err("could not get task request")?;
+    if request.status != TaskStatus::PendingValidation as u8 {
+        log::info!(
+            "Task {} has moved to {}, not responding.",
+            task_id,
+            TaskStatus::try_from(request.status).unwrap_or_default()
+        );
+        return Ok(None);
+    }
+    let validations = node.get_task_validations(task_id).await?;
+    if validations.len() as u64 >= request.parameters.numValidations.to::<u64>() {
+        log::info!(
+            "Task {} has all of its validations, not responding.",
+            task_id
+        );
+        return Ok(None);
+    }
+    if validations.iter().any(|v| v.validator == node.address()) {
+        return Err(eyre!("Already validated {}", task_id));
+    }
+
+    if node.config.dry_run {
+        log::info!(
+            "Dry run, not responding to task {} with nonce {}, scores: {:?}, metadata: {}",
+            task_id,
+            nonce,
+            scores,
+            entry.metadata.as_deref().unwrap_or_default()
+        );
+        entry.set_dry_run();
+        return Ok(None);
+    }
 
     // respond
     log::debug!("Responding with validation");
+    submitted.set(true);
     let tx_receipt = node
         .respond_validation(task_id, scores, metadata, nonce)
         .await?;
@@ -0,0 +1,120 @@
+use alloy::primitives::utils::format_units;
+use std::time::Duration;
+
+/// Strategy to choose the priority fee (tip) per gas of the transactions.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PriorityFee {
+    /// The default estimation of the provider, from the recent blocks.
+    #[default]
+    Estimated,
+    /// A fixed priority fee per gas, in wei.
+    Fixed(u128),
+    /// The given percentile (0 to 100) of the priority fees paid within the recent blocks.
+    Percentile(f64),
+}
+
+impl std::fmt::Display for PriorityFee {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PriorityFee::Estimated => write!(f, "estimated"),
+            PriorityFee::Fixed(fee) => write!(f, "{} gwei", format_gwei(*fee)),
+            PriorityFee::Percentile(percentile) => write!(f, "p{}", percentile),
+        }
+    }
+}
+
+/// EIP-1559 fee policy of the transactions sent by the node.
+///
+/// The default policy leaves the fees to the provider, as if there was no policy.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FeePolicy {
+    /// Maximum fee per gas to offer in wei, the estimated fees are capped to this.
+    pub max_fee_per_gas: Option<u128>,
+    /// Strategy to choose the priority fee per gas.
+    pub priority_fee: PriorityFee,
+    /// Ceiling for the base fee plus the priority fee per gas in wei, above which
+    /// transactions are not sent at all and the tasks are deferred instead.
+    pub fee_ceiling: Option<u128>,
+}
+
+impl FeePolicy {
+    /// Returns `true` if the fees are left to the provider.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl std::fmt::Display for FeePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "priority fee: {}", self.priority_fee)?;
+        if let Some(max_fee_per_gas) = self.max_fee_per_gas {
+            write!(f, ", max fee: {} gwei", format_gwei(max_fee_per_gas))?;
+        }
+        if let Some(fee_ceiling) = self.fee_ceiling {
+            write!(f, ", ceiling: {} gwei", format_gwei(fee_ceiling))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Policy to replace the transactions that are not mined in time, i.e. that are stuck in the mempool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplacementPolicy {
+    /// Time to wait for a transaction to be mined before replacing it.
+    pub timeout: Duration,
+    /// Percentage to bump the fees by for each replacement, most nodes require at least 10%.
+    pub fee_bump_percent: u64,
+    /// Maximum number of replacements with bumped fees, where the maximum fee per gas
+    /// of the fee policy is not exceeded either way.
+    pub max_replacements: u32,
+    /// Whether to cancel the transaction with a zero-value transfer to self
+    /// once there are no replacements left, so that the next transactions are not blocked.
+    pub cancel: bool,
+}
+
+impl Default for ReplacementPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(60),
+            fee_bump_percent: 20,
+            max_replacements: 3,
+            cancel: false,
+        }
+    }
+}
+
+impl ReplacementPolicy {
+    /// Bumps the given fee per gas by the percentage of the policy, at least by 1 wei.
+    pub fn bump(&self, fee: u128) -> u128 {
+        let bump = fee.saturating_mul(self.fee_bump_percent as u128) / 100;
+        fee.saturating_add(bump.max(1))
+    }
+}
+
+/// Formats a wei amount per gas in gwei, without the trailing zeros.
+pub fn format_gwei(amount: u128) -> String {
+    match format_units(amount, "gwei") {
+        Ok(gwei) => gwei.trim_end_matches('0').trim_end_matches('.').to_string(),
+        Err(_) => format!("{} wei", amount),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_gwei() {
+        assert_eq!(format_gwei(30_000_000_000), "30");
+        assert_eq!(format_gwei(1_500_000_000), "1.5");
+        assert_eq!(format_gwei(0), "0");
+    }
+
+    #[test]
+    fn test_replacement_bump() {
+        let policy = ReplacementPolicy::default();
+        assert_eq!(policy.bump(1_000_000_000), 1_200_000_000);
+        assert_eq!(policy.bump(0), 1);
+    }
+}
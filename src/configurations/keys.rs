@@ -0,0 +1,142 @@
+use alloy::{
+    hex,
+    signers::local::{
+        coins_bip39::{English, Mnemonic},
+        PrivateKeySigner,
+    },
+};
+use eyre::{Context, Result};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Default derivation path of the mnemonics, i.e. the first account of the wallet.
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0/0";
+
+/// A 32-byte secret key that is zeroized when dropped.
+pub type SecretKey = Zeroizing<[u8; 32]>;
+
+/// Decodes a secret key from a hexadecimal string, with or without the `0x` prefix,
+/// without leaving a copy of it behind.
+pub fn secret_key_from_hex(value: &str) -> Result<SecretKey> {
+    let mut secret_key = SecretKey::new([0; 32]);
+    hex::decode_to_slice(
+        value.strip_prefix("0x").unwrap_or(value),
+        secret_key.as_mut_slice(),
+    )
+    .wrap_err("could not hex-decode secret key")?;
+
+    Ok(secret_key)
+}
+
+/// Creates a signer from the given secret key, which is zeroized afterwards.
+pub fn signer_from_secret_key(secret_key: SecretKey) -> Result<PrivateKeySigner> {
+    PrivateKeySigner::from_slice(secret_key.as_slice()).wrap_err("could not parse private key")
+}
+
+/// Decrypts an Ethereum V3 JSON keystore with the given password.
+pub fn signer_from_keystore(path: impl AsRef<Path>, password: &str) -> Result<PrivateKeySigner> {
+    let path = path.as_ref();
+    PrivateKeySigner::decrypt_keystore(path, password)
+        .wrap_err_with(|| format!("could not decrypt keystore {}", path.display()))
+}
+
+/// Derives a signer from a BIP-39 mnemonic at the given derivation path, e.g. [`DEFAULT_DERIVATION_PATH`].
+///
+/// The mnemonic is used directly instead of a `MnemonicBuilder`, which would keep a copy of the phrase.
+pub fn signer_from_mnemonic(phrase: &str, derivation_path: &str) -> Result<PrivateKeySigner> {
+    let mnemonic =
+        Mnemonic::<English>::new_from_phrase(phrase).wrap_err("could not parse mnemonic")?;
+    let derived = mnemonic
+        .derive_key(derivation_path, None)
+        .wrap_err_with(|| format!("could not derive key at {}", derivation_path))?;
+
+    Ok(PrivateKeySigner::from_signing_key(Clone::clone(
+        AsRef::as_ref(&derived),
+    )))
+}
+
+/// Reads a secret such as a password or a mnemonic from the given file, without the trailing newline.
+///
+/// The contents are zeroized when dropped.
+pub fn read_secret_file(path: impl AsRef<Path>) -> Result<Zeroizing<String>> {
+    let path = path.as_ref();
+    let contents = Zeroizing::new(
+        std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("could not read {}", path.display()))?,
+    );
+
+    Ok(Zeroizing::new(
+        contents.trim_end_matches(['\n', '\r']).to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{address, B256};
+    use std::str::FromStr;
+
+    /// Mnemonic of Anvil/Hardhat accounts.
+    const MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+    #[test]
+    fn test_signer_from_mnemonic() {
+        let signer = signer_from_mnemonic(MNEMONIC, DEFAULT_DERIVATION_PATH).unwrap();
+        assert_eq!(
+            signer.address(),
+            address!("f39Fd6e51aad88F6F4ce6aB8827279cffFb92266")
+        );
+
+        let signer = signer_from_mnemonic(MNEMONIC, "m/44'/60'/0'/0/1").unwrap();
+        assert_eq!(
+            signer.address(),
+            address!("70997970C51812dc3A010C7d01b50e0d17dc79C8")
+        );
+
+        assert!(signer_from_mnemonic(MNEMONIC, "not/a/path").is_err());
+    }
+
+    #[test]
+    fn test_secret_key_from_hex() {
+        let hex_str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        let expected = B256::from_str(hex_str).unwrap();
+        assert_eq!(*secret_key_from_hex(hex_str).unwrap(), expected.0);
+        assert_eq!(
+            *secret_key_from_hex(&format!("0x{}", hex_str)).unwrap(),
+            expected.0
+        );
+
+        assert!(secret_key_from_hex("0x1234").is_err());
+        assert!(secret_key_from_hex(&"zz".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_signer_from_keystore() {
+        let dir = std::env::temp_dir().join(format!(
+            "dria-oracle-keystore-test-{}",
+            rand::random::<u64>()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let secret_key = SecretKey::new(rand::random());
+        let (signer, name) = PrivateKeySigner::encrypt_keystore(
+            &dir,
+            &mut rand::thread_rng(),
+            secret_key.as_slice(),
+            "password",
+            None,
+        )
+        .unwrap();
+
+        let password_file = dir.join("password");
+        std::fs::write(&password_file, "password\n").unwrap();
+        let password = read_secret_file(&password_file).unwrap();
+        let decrypted = signer_from_keystore(dir.join(&name), &password).unwrap();
+        assert_eq!(decrypted.address(), signer.address());
+        assert!(signer_from_keystore(dir.join(&name), "wrong").is_err());
+
+        let from_key = signer_from_secret_key(secret_key).unwrap();
+        assert_eq!(from_key.address(), signer.address());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
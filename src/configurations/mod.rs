@@ -1,10 +1,21 @@
+mod fees;
+pub use fees::*;
+
+mod keys;
+pub use keys::*;
+
+use crate::contracts::ContractAddresses;
 use alloy::{
-    hex::FromHex, network::EthereumWallet, primitives::B256, signers::local::PrivateKeySigner,
+    network::EthereumWallet, primitives::B256, signers::local::PrivateKeySigner,
     transports::http::reqwest::Url,
 };
 
 use eyre::{Context, Result};
 use std::env;
+use zeroize::Zeroizing;
+
+/// Default number of blocks to query at once when fetching logs.
+pub const DEFAULT_LOG_CHUNK_SIZE: u64 = 10_000;
 
 /// Configuration for the Dria Oracle.
 #[derive(Debug, Clone)]
@@ -12,9 +23,27 @@ pub struct DriaOracleConfig {
     /// Wallet for the oracle.
     pub wallet: EthereumWallet,
     /// RPC URL for the oracle, decides the connected chain.
+    ///
+    /// Can be an HTTP, WebSocket or IPC (as `file://`) URL.
     pub rpc_url: Url,
+    /// Fallback RPC URLs by their priorities, that are used when the RPC URL is not healthy.
+    ///
+    /// They must be connected to the same chain as the RPC URL.
+    pub fallback_rpc_urls: Vec<Url>,
+    /// Optional contract addresses, otherwise they are chosen by the chain id of the RPC.
+    pub addresses: Option<ContractAddresses>,
+    /// Fee policy of the transactions.
+    pub fee_policy: FeePolicy,
+    /// Optional policy to replace the transactions that are not mined in time.
+    pub replacement: Option<ReplacementPolicy>,
     /// Optional transaction timeout, is useful to avoid getting stuck at `get_receipt()` when making a transaction.
     pub tx_timeout: Option<std::time::Duration>,
+    /// Maximum number of blocks to query at once when fetching logs.
+    pub log_chunk_size: u64,
+    /// Whether to compute the responses without uploading or sending them.
+    pub dry_run: bool,
+    /// Whether to simulate the transactions with `eth_call` before sending them, so that reverts do not cost gas.
+    pub simulate: bool,
 }
 
 impl Default for DriaOracleConfig {
@@ -27,13 +56,27 @@ impl DriaOracleConfig {
     pub fn new(secret_key: &B256, rpc_url: Url) -> Result<Self> {
         let signer =
             PrivateKeySigner::from_bytes(secret_key).wrap_err("Could not parse private key")?;
+
+        Ok(Self::from_signer(signer, rpc_url))
+    }
+
+    /// Creates a new configuration with the given signer, e.g. one that is decrypted from a keystore
+    /// or derived from a mnemonic; see [`signer_from_keystore`] and [`signer_from_mnemonic`].
+    pub fn from_signer(signer: PrivateKeySigner, rpc_url: Url) -> Self {
         let wallet = EthereumWallet::from(signer);
 
-        Ok(Self {
+        Self {
             wallet,
             rpc_url,
+            fallback_rpc_urls: Vec::new(),
+            addresses: None,
+            fee_policy: FeePolicy::default(),
+            replacement: None,
             tx_timeout: None,
-        })
+            log_chunk_size: DEFAULT_LOG_CHUNK_SIZE,
+            dry_run: false,
+            simulate: true,
+        }
     }
 
     /// Change the transaction timeout.
@@ -45,22 +88,69 @@ impl DriaOracleConfig {
         self
     }
 
+    /// Change the fee policy of the transactions.
+    ///
+    /// Registrations, claims & responses all follow this policy.
+    pub fn with_fee_policy(mut self, fee_policy: FeePolicy) -> Self {
+        self.fee_policy = fee_policy;
+        self
+    }
+
+    /// Change the replacement policy of the transactions.
+    ///
+    /// If there is no policy, transactions are waited for until the transaction timeout.
+    pub fn with_replacement(mut self, replacement: Option<ReplacementPolicy>) -> Self {
+        self.replacement = replacement;
+        self
+    }
+
+    /// Change the maximum number of blocks to query at once when fetching logs.
+    ///
+    /// Most hosted RPCs limit the block range of `eth_getLogs`, so larger ranges are split into chunks of this size.
+    pub fn with_log_chunk_size(mut self, log_chunk_size: u64) -> Self {
+        self.log_chunk_size = log_chunk_size;
+        self
+    }
+
+    /// Change the dry-run mode.
+    ///
+    /// In dry-run mode, tasks are handled until right before responding, without uploading
+    /// anything to storage or sending any transactions, and the responses are logged instead.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Change whether the transactions are simulated before being sent.
+    ///
+    /// Simulation costs an `eth_call` per transaction, which can be disabled for latency-sensitive setups;
+    /// then, reverts are only seen after the transaction is mined.
+    pub fn with_simulation(mut self, simulate: bool) -> Self {
+        self.simulate = simulate;
+        self
+    }
+
     /// Creates the config from the environment variables.
     ///
     /// Required environment variables:
     /// - `SECRET_KEY`
-    /// - `RPC_URL`
+    /// - `RPC_URL`, where the URLs after the first one are the fallbacks if it is a comma-separated list
     pub fn new_from_env() -> Result<Self> {
-        // parse private key
-        let private_key_hex = env::var("SECRET_KEY").wrap_err("SECRET_KEY is not set")?;
-        let secret_key =
-            B256::from_hex(private_key_hex).wrap_err("could not hex-decode secret key")?;
+        // parse private key, where both the hex string and the key are zeroized when dropped
+        let private_key_hex =
+            Zeroizing::new(env::var("SECRET_KEY").wrap_err("SECRET_KEY is not set")?);
+        let signer = signer_from_secret_key(secret_key_from_hex(&private_key_hex)?)?;
 
         // parse rpc url
         let rpc_url_env = env::var("RPC_URL").wrap_err("RPC_URL is not set")?;
-        let rpc_url = Url::parse(&rpc_url_env).wrap_err("could not parse RPC_URL")?;
-
-        Self::new(&secret_key, rpc_url)
+        let mut rpc_urls = rpc_url_env
+            .split(',')
+            .map(|url| Url::parse(url.trim()))
+            .collect::<Result<Vec<_>, _>>()
+            .wrap_err("could not parse RPC_URL")?;
+        let rpc_url = rpc_urls.remove(0);
+
+        Ok(Self::from_signer(signer, rpc_url).with_fallback_rpc_urls(rpc_urls))
     }
 
     /// Creates a new local configuration.
@@ -82,6 +172,34 @@ impl DriaOracleConfig {
         self
     }
 
+    /// Change the fallback RPC URLs, the first one has the highest priority.
+    pub fn with_fallback_rpc_urls(mut self, fallback_rpc_urls: Vec<Url>) -> Self {
+        self.fallback_rpc_urls = fallback_rpc_urls;
+        self
+    }
+
+    /// Change the contract addresses, instead of choosing them by the chain id of the RPC.
+    ///
+    /// This allows connecting to chains that are not known to the node, e.g. a new deployment.
+    pub fn with_addresses(mut self, addresses: Option<ContractAddresses>) -> Self {
+        self.addresses = addresses;
+        self
+    }
+
+    /// Returns the RPC URL followed by the fallback RPC URLs.
+    pub fn rpc_urls(&self) -> Vec<Url> {
+        std::iter::once(self.rpc_url.clone())
+            .chain(self.fallback_rpc_urls.iter().cloned())
+            .collect()
+    }
+
+    /// Returns `true` if the RPC connection supports subscriptions, i.e. it is a WebSocket or IPC connection.
+    ///
+    /// Connections with fallbacks do not support subscriptions, as they can move between endpoints.
+    pub fn is_pubsub(&self) -> bool {
+        self.fallback_rpc_urls.is_empty() && matches!(self.rpc_url.scheme(), "ws" | "wss" | "file")
+    }
+
     /// Change the underlying wallet.
     pub fn with_wallet(mut self, wallet: EthereumWallet) -> Self {
         self.wallet = wallet;
@@ -0,0 +1,568 @@
+use crate::{
+    contracts::{
+        LLMOracleTask::{TaskResponse, TaskValidation},
+        OracleKind,
+    },
+    storage::{ArweaveStorage, IsExternalStorage},
+    DriaOracle,
+};
+use alloy::{
+    primitives::{utils::format_ether, Address, Bytes, TxHash, B256, U256},
+    rpc::types::TransactionReceipt,
+};
+use eyre::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::{
+    fs,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Schema of the journal, where big numbers are stored as decimal strings.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS tasks (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    task_id      TEXT NOT NULL,
+    kind         TEXT NOT NULL,
+    protocol     TEXT NOT NULL,
+    input_hash   TEXT,
+    model        TEXT,
+    raw_output   TEXT,
+    output       TEXT,
+    metadata     TEXT,
+    storage_keys TEXT NOT NULL,
+    nonce        TEXT,
+    tx_hash      TEXT,
+    gas_used     INTEGER,
+    gas_price    TEXT,
+    outcome      TEXT NOT NULL,
+    error        TEXT,
+    started_at   INTEGER NOT NULL,
+    duration_ms  INTEGER NOT NULL,
+    score        TEXT,
+    reward       TEXT,
+    responder    TEXT
+);
+CREATE INDEX IF NOT EXISTS tasks_task_id ON tasks (task_id);
+";
+
+/// Outcome of a task that has been responded to.
+const RESPONDED: &str = "responded";
+/// Outcome of a task that would have been responded to, if it was not a dry run.
+const DRY_RUN: &str = "dry-run";
+
+/// A single attempt of handling a task, along with what the node has responded with.
+///
+/// The entry is filled by the handlers as they go, and the `score` & `reward`
+/// are added later on, once the task is completed on-chain.
+#[derive(Debug, Clone, Default)]
+pub struct JournalEntry {
+    pub task_id: U256,
+    /// Oracle kind that the task is handled as, e.g. `generator`.
+    pub kind: String,
+    pub protocol: String,
+    /// Keccak256 hash of the request input.
+    pub input_hash: Option<B256>,
+    pub model: Option<String>,
+    /// Output of the workflow, before post-processing.
+    pub raw_output: Option<String>,
+    /// Output that is sent on-chain, which may be a storage key.
+    pub output: Option<String>,
+    /// Metadata that is sent on-chain, which may be a storage key.
+    pub metadata: Option<String>,
+    /// Keys of the values that are uploaded to storage.
+    pub storage_keys: Vec<String>,
+    pub nonce: Option<U256>,
+    pub tx_hash: Option<TxHash>,
+    pub gas_used: Option<u64>,
+    /// Effective gas price of the response transaction, in wei.
+    pub gas_price: Option<u128>,
+    /// One of `responded`, `dry-run`, `skipped` or `failed`.
+    pub outcome: String,
+    pub error: Option<String>,
+    /// Time the task is started at, in milliseconds since the Unix epoch.
+    pub started_at: u64,
+    pub duration_ms: u64,
+    /// Final score of the response, for generators.
+    pub score: Option<U256>,
+    /// Reward that is observed after the task is completed.
+    pub reward: Option<U256>,
+    /// Address of the identity that has handled the task, `None` for the entries
+    /// that are recorded before the node had multiple identities.
+    pub responder: Option<Address>,
+}
+
+impl JournalEntry {
+    /// Starts a new entry for the given task, handled by the given responder.
+    pub fn new(task_id: U256, kind: OracleKind, protocol: String, responder: Address) -> Self {
+        Self {
+            task_id,
+            kind: kind.to_string().to_lowercase(),
+            protocol,
+            responder: Some(responder),
+            started_at: now_millis(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the output that is sent on-chain, and records it if it is a storage key.
+    pub fn set_output(&mut self, output: &Bytes) {
+        let output = String::from_utf8_lossy(output).to_string();
+        self.record_storage_key(&output);
+        self.output = Some(output);
+    }
+
+    /// Sets the metadata that is sent on-chain, and records it if it is a storage key.
+    pub fn set_metadata(&mut self, metadata: &Bytes) {
+        let metadata = String::from_utf8_lossy(metadata).to_string();
+        self.record_storage_key(&metadata);
+        self.metadata = Some(metadata);
+    }
+
+    fn record_storage_key(&mut self, value: &str) {
+        if let Some(key) = ArweaveStorage::is_key(value) {
+            self.storage_keys.push(key.arweave);
+        }
+    }
+
+    /// Marks the entry as a dry run, i.e. the response is computed but not sent.
+    pub fn set_dry_run(&mut self) {
+        self.outcome = DRY_RUN.to_string();
+    }
+
+    /// Finishes the entry w.r.t the result of the task.
+    pub fn finish(&mut self, result: &Result<Option<TransactionReceipt>>) {
+        self.duration_ms = now_millis().saturating_sub(self.started_at);
+        match result {
+            Ok(Some(receipt)) => {
+                self.outcome = RESPONDED.to_string();
+                self.tx_hash = Some(receipt.transaction_hash);
+                self.gas_used = Some(U256::from(receipt.gas_used).saturating_to());
+                self.gas_price = Some(receipt.effective_gas_price);
+            }
+            Ok(None) if self.outcome == DRY_RUN => {}
+            Ok(None) => self.outcome = String::from("skipped"),
+            Err(e) => {
+                self.outcome = String::from("failed");
+                self.error = Some(format!("{:#}", e));
+            }
+        }
+    }
+
+    /// Gas cost of the response transaction, in wei.
+    pub fn cost(&self) -> Option<U256> {
+        Some(U256::from(self.gas_used?) * U256::from(self.gas_price?))
+    }
+}
+
+impl std::fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_none = |value: Option<String>| value.unwrap_or_else(|| String::from("-"));
+
+        write!(
+            f,
+            "Task {} as {} ({})\nResponder:  {}\nProtocol:   {}\nStarted:    {} (took {}ms)\nInput hash: {}\nModel:      {}\nRaw output: {}\nOutput:     {}\nMetadata:   {}\nStorage:    {}\nNonce:      {}\nTx hash:    {}\nCost:       {}\nScore:      {}\nReward:     {}",
+            self.task_id,
+            self.kind,
+            self.outcome,
+            or_none(self.responder.map(|a| a.to_string())),
+            self.protocol,
+            self.started_at,
+            self.duration_ms,
+            or_none(self.input_hash.map(|h| h.to_string())),
+            or_none(self.model.clone()),
+            or_none(self.raw_output.clone()),
+            or_none(self.output.clone()),
+            or_none(self.metadata.clone()),
+            self.storage_keys.join(", "),
+            or_none(self.nonce.map(|n| n.to_string())),
+            or_none(self.tx_hash.map(|h| h.to_string())),
+            or_none(self.cost().map(format_ether)),
+            or_none(self.score.map(|s| s.to_string())),
+            or_none(self.reward.map(format_ether)),
+        )?;
+        if let Some(error) = &self.error {
+            write!(f, "\nError:      {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A local journal of the handled tasks & their outcomes, stored as an SQLite database
+/// within the data directory.
+///
+/// Each attempt of a task is a separate entry, e.g. a failed attempt followed by a successful retry.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Journal {
+    /// Opens the journal for the given chain & coordinator within the data directory,
+    /// creating the directory & the database if they do not exist.
+    pub fn open(data_dir: &Path, chain_id: u64, coordinator: Address) -> Result<Self> {
+        fs::create_dir_all(data_dir).wrap_err("could not create data directory")?;
+        let path = data_dir.join(format!("journal-{}-{}.sqlite", chain_id, coordinator));
+
+        let conn = Connection::open(&path).wrap_err("could not open journal")?;
+        conn.execute_batch(SCHEMA)
+            .wrap_err("could not create journal schema")?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Opens an in-memory journal.
+    #[cfg(test)]
+    fn open_in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch(SCHEMA)?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Adds an entry to the journal.
+    pub fn record(&self, entry: &JournalEntry) -> Result<()> {
+        let conn = self.conn.lock().expect("journal lock is poisoned");
+        conn.execute(
+            "INSERT INTO tasks (task_id, kind, protocol, input_hash, model, raw_output, output, metadata, storage_keys, nonce, tx_hash, gas_used, gas_price, outcome, error, started_at, duration_ms, score, reward, responder)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
+            params![
+                entry.task_id.to_string(),
+                entry.kind,
+                entry.protocol,
+                entry.input_hash.map(|h| h.to_string()),
+                entry.model,
+                entry.raw_output,
+                entry.output,
+                entry.metadata,
+                entry.storage_keys.join(","),
+                entry.nonce.map(|n| n.to_string()),
+                entry.tx_hash.map(|h| h.to_string()),
+                entry.gas_used,
+                entry.gas_price.map(|p| p.to_string()),
+                entry.outcome,
+                entry.error,
+                entry.started_at,
+                entry.duration_ms,
+                entry.score.map(|s| s.to_string()),
+                entry.reward.map(|r| r.to_string()),
+                entry.responder.map(|a| a.to_string()),
+            ],
+        )
+        .wrap_err("could not write to journal")?;
+
+        Ok(())
+    }
+
+    /// Returns the entries of the given task, or the latest entries if no task is given,
+    /// from the newest to the oldest.
+    pub fn entries(&self, task_id: Option<U256>, limit: usize) -> Result<Vec<JournalEntry>> {
+        let conn = self.conn.lock().expect("journal lock is poisoned");
+        let mut stmt = conn.prepare(
+            "SELECT task_id, kind, protocol, input_hash, model, raw_output, output, metadata, storage_keys, nonce, tx_hash, gas_used, gas_price, outcome, error, started_at, duration_ms, score, reward, responder
+             FROM tasks WHERE ?1 IS NULL OR task_id = ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        let entries = stmt
+            .query_map(
+                params![task_id.map(|id| id.to_string()), limit as i64],
+                read_entry,
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .wrap_err("could not read journal")?;
+
+        Ok(entries)
+    }
+
+    /// Returns the kinds & responders that the given task has been responded as.
+    fn responses(&self, task_id: U256) -> Result<Vec<(String, Option<String>)>> {
+        let conn = self.conn.lock().expect("journal lock is poisoned");
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT kind, responder FROM tasks WHERE task_id = ?1 AND outcome = ?2",
+        )?;
+        let responses = stmt
+            .query_map(params![task_id.to_string(), RESPONDED], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(responses)
+    }
+
+    /// Sets the score & reward of the responded entry of a task by the given responder.
+    fn set_outcome(
+        &self,
+        task_id: U256,
+        kind: &str,
+        responder: Option<&str>,
+        score: Option<U256>,
+        reward: U256,
+    ) -> Result<()> {
+        let conn = self.conn.lock().expect("journal lock is poisoned");
+        let id: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM tasks WHERE task_id = ?1 AND kind = ?2 AND outcome = ?3 AND responder IS ?4 ORDER BY id DESC LIMIT 1",
+                params![task_id.to_string(), kind, RESPONDED, responder],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(id) = id {
+            conn.execute(
+                "UPDATE tasks SET score = ?1, reward = ?2 WHERE id = ?3",
+                params![score.map(|s| s.to_string()), reward.to_string(), id],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Records the final score & reward of the responses to a completed task, if there are any.
+    ///
+    /// The reward is computed w.r.t the finalization of the coordinator, where a generator is paid
+    /// if its score is not below the mean by more than the deviation factor times the standard
+    /// deviation, and a validator is paid for each generation that its score is within that range.
+    ///
+    /// Entries without a responder are considered to be responded by the given node.
+    pub async fn observe_completion(&self, node: &DriaOracle, task_id: U256) -> Result<()> {
+        let responses = self.responses(task_id)?;
+        if responses.is_empty() {
+            return Ok(());
+        }
+
+        let request = node.get_task_request(task_id).await?;
+        let (generation_deviation, validation_deviation) = node.get_deviation_factors().await?;
+        for (kind, responder) in responses {
+            let address = match &responder {
+                Some(responder) => responder.parse()?,
+                None => node.address(),
+            };
+            let (score, reward) = if kind == OracleKind::Validator.to_string().to_lowercase() {
+                let validations = node.get_task_validations(task_id).await?;
+                let payouts = validation_payouts(address, &validations, validation_deviation);
+                (None, request.validatorFee * U256::from(payouts))
+            } else {
+                let responses = node.get_task_responses(task_id).await?;
+                match generation_outcome(address, &responses, generation_deviation) {
+                    Some((score, true)) => (Some(score), request.generatorFee),
+                    Some((score, false)) => (Some(score), U256::ZERO),
+                    None => (None, U256::ZERO),
+                }
+            };
+
+            log::info!(
+                "Task {} is completed, reward of {} as {}: {}",
+                task_id,
+                address,
+                kind,
+                format_ether(reward)
+            );
+            self.set_outcome(task_id, &kind, responder.as_deref(), score, reward)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the final score of the given responder, and whether it is paid.
+fn generation_outcome(
+    responder: Address,
+    responses: &[TaskResponse],
+    deviation_factor: u64,
+) -> Option<(U256, bool)> {
+    let score = responses.iter().find(|r| r.responder == responder)?.score;
+    let scores = responses.iter().map(|r| r.score).collect::<Vec<_>>();
+    let (mean, stddev) = mean_stddev(&scores);
+    let min_score = mean.saturating_sub(stddev * U256::from(deviation_factor));
+
+    Some((score, score >= min_score))
+}
+
+/// Returns the number of generations that the given validator is paid for.
+fn validation_payouts(
+    validator: Address,
+    validations: &[TaskValidation],
+    deviation_factor: u64,
+) -> u64 {
+    let Some(own) = validations.iter().find(|v| v.validator == validator) else {
+        return 0;
+    };
+
+    let mut payouts = 0;
+    for (idx, score) in own.scores.iter().enumerate() {
+        let scores = validations
+            .iter()
+            .filter_map(|v| v.scores.get(idx).copied())
+            .collect::<Vec<_>>();
+        let (mean, stddev) = mean_stddev(&scores);
+        let range = stddev * U256::from(deviation_factor);
+        if *score >= mean.saturating_sub(range) && *score <= mean.saturating_add(range) {
+            payouts += 1;
+        }
+    }
+
+    payouts
+}
+
+/// Integer mean & population standard deviation, as computed by the coordinator.
+fn mean_stddev(values: &[U256]) -> (U256, U256) {
+    if values.is_empty() {
+        return (U256::ZERO, U256::ZERO);
+    }
+
+    let n = U256::from(values.len());
+    let mean = values.iter().fold(U256::ZERO, |acc, v| acc + v) / n;
+    let variance = values
+        .iter()
+        .map(|v| {
+            let diff = if *v > mean { *v - mean } else { mean - *v };
+            diff * diff
+        })
+        .fold(U256::ZERO, |acc, v| acc + v)
+        / n;
+
+    (mean, variance.root(2))
+}
+
+fn read_entry(row: &Row) -> rusqlite::Result<JournalEntry> {
+    fn parse<T: FromStr>(value: Option<String>) -> Option<T> {
+        value.and_then(|value| value.parse().ok())
+    }
+
+    let storage_keys: String = row.get(8)?;
+    Ok(JournalEntry {
+        task_id: parse(row.get(0)?).unwrap_or_default(),
+        kind: row.get(1)?,
+        protocol: row.get(2)?,
+        input_hash: parse(row.get(3)?),
+        model: row.get(4)?,
+        raw_output: row.get(5)?,
+        output: row.get(6)?,
+        metadata: row.get(7)?,
+        storage_keys: storage_keys
+            .split(',')
+            .filter(|key| !key.is_empty())
+            .map(String::from)
+            .collect(),
+        nonce: parse(row.get(9)?),
+        tx_hash: parse(row.get(10)?),
+        gas_used: row.get(11)?,
+        gas_price: parse(row.get(12)?),
+        outcome: row.get(13)?,
+        error: row.get(14)?,
+        started_at: row.get(15)?,
+        duration_ms: row.get(16)?,
+        score: parse(row.get(17)?),
+        reward: parse(row.get(18)?),
+        responder: parse(row.get(19)?),
+    })
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal() {
+        let journal = Journal::open_in_memory().unwrap();
+        let task_id = U256::from(1234);
+
+        let responder = Address::with_last_byte(1);
+        let mut entry = JournalEntry::new(
+            task_id,
+            OracleKind::Generator,
+            "test/0.1.0".into(),
+            responder,
+        );
+        entry.model = Some("gpt-4o-mini".into());
+        entry.set_output(&Bytes::from_static(br#"{"arweave":"abc"}"#));
+        entry.finish(&Err(eyre::eyre!("connection refused")));
+        journal.record(&entry).unwrap();
+
+        entry.finish(&Ok(None));
+        entry.outcome = RESPONDED.to_string();
+        entry.gas_used = Some(100);
+        entry.gas_price = Some(2);
+        journal.record(&entry).unwrap();
+
+        journal
+            .set_outcome(
+                task_id,
+                "generator",
+                Some(&responder.to_string()),
+                Some(U256::from(5)),
+                U256::from(10),
+            )
+            .unwrap();
+
+        let entries = journal.entries(Some(task_id), 10).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].outcome, RESPONDED);
+        assert_eq!(entries[0].reward, Some(U256::from(10)));
+        assert_eq!(entries[0].cost(), Some(U256::from(200)));
+        assert_eq!(entries[0].storage_keys, vec!["abc".to_string()]);
+        assert_eq!(entries[0].responder, Some(responder));
+        assert_eq!(entries[1].outcome, "failed");
+        assert_eq!(entries[1].reward, None);
+        assert!(journal.entries(Some(U256::from(1)), 10).unwrap().is_empty());
+        assert_eq!(journal.entries(None, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_payouts() {
+        let values = [2u64, 4, 4, 4, 5, 5, 7, 9].map(U256::from);
+        assert_eq!(mean_stddev(&values), (U256::from(5), U256::from(2)));
+
+        let me = Address::with_last_byte(1);
+        let response = |responder: Address, score: u64| TaskResponse {
+            responder,
+            nonce: U256::ZERO,
+            score: U256::from(score),
+            output: Bytes::new(),
+            metadata: Bytes::new(),
+        };
+        // mean is 6 and stddev is 3, so scores below 3 are not paid with a factor of 1
+        let responses = vec![
+            response(me, 1),
+            response(Address::ZERO, 10),
+            response(Address::ZERO, 7),
+        ];
+        assert_eq!(
+            generation_outcome(me, &responses, 1),
+            Some((U256::from(1), false))
+        );
+        assert_eq!(
+            generation_outcome(me, &responses, 2),
+            Some((U256::from(1), true))
+        );
+        assert_eq!(
+            generation_outcome(Address::with_last_byte(2), &responses, 1),
+            None
+        );
+
+        let validation = |validator: Address, scores: [u64; 2]| TaskValidation {
+            validator,
+            nonce: U256::ZERO,
+            scores: scores.map(U256::from).to_vec(),
+            metadata: Bytes::new(),
+        };
+        let validations = vec![
+            validation(me, [5, 1]),
+            validation(Address::ZERO, [5, 9]),
+            validation(Address::ZERO, [5, 8]),
+        ];
+        assert_eq!(validation_payouts(me, &validations, 1), 1);
+    }
+}
@@ -4,19 +4,34 @@ mod cli;
 pub use cli::cli;
 
 mod node;
-pub use node::DriaOracle;
+pub use node::{
+    DriaOracle, FailoverTransport, FeeCeilingExceeded, NonceManager, SimulationReverted,
+};
 
 /// Node configurations.
 mod configurations;
-pub use configurations::DriaOracleConfig;
+pub use configurations::{signer_from_keystore, signer_from_mnemonic, DEFAULT_DERIVATION_PATH};
+pub use configurations::{DriaOracleConfig, FeePolicy, PriorityFee, ReplacementPolicy};
 
 mod compute;
-pub use compute::{handle_request, mine_nonce};
+pub use compute::{
+    handle_request, mine_nonce, ProfitEstimate, ProfitabilityGate, ProtocolFilter, ProtocolPattern,
+};
 
 mod contracts;
 pub use contracts::{bytes32_to_string, bytes_to_string, string_to_bytes, string_to_bytes32};
+pub use contracts::{ContractAddresses, OracleKind, TaskStatus};
 pub use contracts::{OracleCoordinator, OracleRegistry, ERC20, WETH};
-pub use contracts::{OracleKind, TaskStatus};
+
+/// Prometheus metrics of the node.
+mod metrics;
+
+/// Logging & tracing of the node.
+pub mod telemetry;
+
+/// Local journal of the handled tasks.
+mod journal;
+pub use journal::{Journal, JournalEntry};
 
 /// External data storage, such as Arweave.
 pub mod storage;
@@ -1,21 +1,22 @@
+use dria_oracle::telemetry::{self, LogFormat};
 use std::env;
+use tracing::level_filters::LevelFilter;
 
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     let dotenv_result = dotenvy::dotenv();
 
     let default_log = if env::var("DEBUG").is_ok() {
-        log::LevelFilter::Debug
+        LevelFilter::DEBUG
     } else {
-        log::LevelFilter::Info
+        LevelFilter::INFO
     };
-    env_logger::builder()
-        .format_timestamp(Some(env_logger::TimestampPrecision::Millis))
-        .filter(None, log::LevelFilter::Off)
-        .filter_module("dria_oracle", default_log)
-        .filter_module("dkn_workflows", default_log)
-        .parse_default_env()
-        .init();
+    let log_format = match env::var("LOG_FORMAT") {
+        Ok(log_format) => log_format.parse()?,
+        Err(_) => LogFormat::default(),
+    };
+    let otlp = env::var("OTEL_EXPORTER_OTLP_ENDPOINT").is_ok();
+    let _telemetry = telemetry::init(log_format, default_log, otlp)?;
 
     // log about env usage
     match dotenv_result {
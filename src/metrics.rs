@@ -0,0 +1,217 @@
+use crate::{
+    contracts::{bytes32_to_string, OracleKind},
+    TaskStatus,
+};
+use alloy::{
+    primitives::{utils::format_ether, FixedBytes, U256},
+    rpc::types::TransactionReceipt,
+};
+use dkn_workflows::Model;
+use eyre::Result;
+use lazy_static::lazy_static;
+use prometheus::{
+    register_gauge_vec, register_histogram_vec, register_int_counter_vec, Encoder, GaugeVec,
+    HistogramVec, IntCounterVec, TextEncoder,
+};
+
+/// Labels of the task counters.
+const TASK_LABELS: [&str; 4] = ["chain", "kind", "protocol", "model"];
+
+/// Buckets for the latency histograms in seconds, from 100ms to ~10 minutes.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0,
+];
+
+lazy_static! {
+    pub static ref TASKS_SEEN: IntCounterVec = register_int_counter_vec!(
+        "dria_oracle_tasks_seen_total",
+        "Number of task events seen.",
+        &TASK_LABELS
+    )
+    .unwrap();
+    pub static ref TASKS_HANDLED: IntCounterVec = register_int_counter_vec!(
+        "dria_oracle_tasks_handled_total",
+        "Number of tasks responded to.",
+        &TASK_LABELS
+    )
+    .unwrap();
+    pub static ref TASKS_IGNORED: IntCounterVec = register_int_counter_vec!(
+        "dria_oracle_tasks_ignored_total",
+        "Number of tasks ignored, e.g. due to filters or already being responded to.",
+        &TASK_LABELS
+    )
+    .unwrap();
+    pub static ref TASKS_FAILED: IntCounterVec = register_int_counter_vec!(
+        "dria_oracle_tasks_failed_total",
+        "Number of tasks that failed with an error.",
+        &TASK_LABELS
+    )
+    .unwrap();
+    pub static ref GENERATION_DURATION: HistogramVec = register_histogram_vec!(
+        "dria_oracle_generation_duration_seconds",
+        "Time spent executing generation workflows.",
+        &["chain", "model"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap();
+    pub static ref VALIDATION_DURATION: HistogramVec = register_histogram_vec!(
+        "dria_oracle_validation_duration_seconds",
+        "Time spent executing validation workflows.",
+        &["chain", "model"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap();
+    pub static ref NONCE_MINING_DURATION: HistogramVec = register_histogram_vec!(
+        "dria_oracle_nonce_mining_duration_seconds",
+        "Time spent mining nonces.",
+        &["chain", "kind"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap();
+    pub static ref TX_RECEIPT_DURATION: HistogramVec = register_histogram_vec!(
+        "dria_oracle_tx_receipt_duration_seconds",
+        "Time between sending a transaction and receiving its receipt.",
+        &["chain", "method"],
+        LATENCY_BUCKETS.to_vec()
+    )
+    .unwrap();
+    pub static ref GAS_USED: IntCounterVec = register_int_counter_vec!(
+        "dria_oracle_gas_used_total",
+        "Total gas used by the transactions of the oracle.",
+        &["chain"]
+    )
+    .unwrap();
+    pub static ref GAS_SPENT: GaugeVec = register_gauge_vec!(
+        "dria_oracle_gas_spent_ether_total",
+        "Total gas fees paid by the transactions of the oracle, in ether.",
+        &["chain", "method"]
+    )
+    .unwrap();
+    pub static ref RPC_ENDPOINT_HEALTHY: GaugeVec = register_gauge_vec!(
+        "dria_oracle_rpc_endpoint_healthy",
+        "Whether an RPC endpoint is healthy w.r.t its latest probe, labeled by its priority & host.",
+        &["endpoint"]
+    )
+    .unwrap();
+    pub static ref BALANCE: GaugeVec = register_gauge_vec!(
+        "dria_oracle_balance",
+        "Current balance of each identity of the oracle, in ether units.",
+        &["chain", "address", "symbol"]
+    )
+    .unwrap();
+    pub static ref CLAIMABLE_REWARDS: GaugeVec = register_gauge_vec!(
+        "dria_oracle_claimable_rewards",
+        "Current claimable rewards of each identity of the oracle, in ether units.",
+        &["chain", "address", "symbol"]
+    )
+    .unwrap();
+}
+
+/// Labels of a task for the task counters, the model is only known once it is chosen.
+#[derive(Debug, Clone, Default)]
+pub struct TaskLabels {
+    chain: String,
+    kind: String,
+    protocol: String,
+    model: String,
+}
+
+impl TaskLabels {
+    /// Creates the labels of a task on the given chain, with the given status & protocol.
+    pub fn new(chain_id: u64, status: TaskStatus, protocol: &FixedBytes<32>) -> Self {
+        let kind = match status {
+            TaskStatus::PendingGeneration => OracleKind::Generator.to_string().to_lowercase(),
+            TaskStatus::PendingValidation => OracleKind::Validator.to_string().to_lowercase(),
+            _ => String::from("none"),
+        };
+
+        Self {
+            chain: chain_id.to_string(),
+            kind,
+            protocol: bytes32_to_string(protocol).unwrap_or_default(),
+            model: String::new(),
+        }
+    }
+
+    /// Sets the model that is used for the task.
+    pub fn set_model(&mut self, model: &Model) {
+        self.model = model.to_string();
+    }
+
+    fn values(&self) -> [&str; 4] {
+        [&self.chain, &self.kind, &self.protocol, &self.model]
+    }
+
+    /// Counts a seen task.
+    pub fn seen(&self) {
+        TASKS_SEEN.with_label_values(&self.values()).inc();
+    }
+
+    /// Counts an ignored task.
+    pub fn ignored(&self) {
+        TASKS_IGNORED.with_label_values(&self.values()).inc();
+    }
+
+    /// Counts the task w.r.t its result, i.e. handled, ignored or failed.
+    pub fn record<T>(&self, result: &Result<Option<T>>) {
+        let counter: &IntCounterVec = match result {
+            Ok(Some(_)) => &TASKS_HANDLED,
+            Ok(None) => &TASKS_IGNORED,
+            Err(_) => &TASKS_FAILED,
+        };
+        counter.with_label_values(&self.values()).inc();
+    }
+}
+
+/// Records the receipt latency & gas of a transaction that is sent on the given chain by the given method.
+pub fn record_receipt(
+    chain_id: u64,
+    method: &str,
+    receipt: &TransactionReceipt,
+    elapsed: std::time::Duration,
+) {
+    let chain = chain_id.to_string();
+    TX_RECEIPT_DURATION
+        .with_label_values(&[&chain, method])
+        .observe(elapsed.as_secs_f64());
+
+    let gas_used = U256::from(receipt.gas_used);
+    GAS_USED
+        .with_label_values(&[&chain])
+        .inc_by(gas_used.saturating_to::<u64>());
+    let fee = gas_used * U256::from(receipt.effective_gas_price);
+    GAS_SPENT
+        .with_label_values(&[&chain, method])
+        .add(to_ether(fee));
+}
+
+/// Converts a wei amount to ether, for the gauges.
+pub fn to_ether(amount: U256) -> f64 {
+    format_ether(amount).parse().unwrap_or_default()
+}
+
+/// Encodes all the registered metrics in the Prometheus text format.
+pub fn gather() -> Result<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_labels() {
+        let protocol = crate::string_to_bytes32("test-protocol/0.1.0".to_string()).unwrap();
+        let mut labels = TaskLabels::new(84532, TaskStatus::PendingGeneration, &protocol);
+        labels.seen();
+        labels.set_model(&Model::GPT4o);
+        labels.record::<()>(&Ok(Some(())));
+
+        let model = Model::GPT4o.to_string();
+        let values = ["84532", "generator", "test-protocol/0.1.0", model.as_str()];
+        assert_eq!(TASKS_HANDLED.with_label_values(&values).get(), 1);
+        assert!(gather().unwrap().contains("dria_oracle_tasks_seen_total"));
+    }
+}
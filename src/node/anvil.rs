@@ -25,7 +25,10 @@ impl DriaOracle {
     /// Note that when Anvil instance is dropped, you will lose the forked chain.
     pub async fn anvil_new(config: DriaOracleConfig) -> Result<(Self, AnvilInstance)> {
         let anvil = Anvil::new().fork(config.rpc_url.to_string()).try_spawn()?;
-        let node = Self::new(config.with_rpc_url(anvil.endpoint_url())).await?;
+        let config = config
+            .with_rpc_url(anvil.endpoint_url())
+            .with_fallback_rpc_urls(Vec::new());
+        let node = Self::new(config).await?;
 
         Ok((node, anvil))
     }
@@ -1,16 +1,41 @@
 use self::OracleCoordinator::getFeeReturn;
-use super::{DriaOracle, DriaOracleProviderTransport};
+use super::DriaOracle;
 use crate::contracts::*;
-use alloy::contract::EventPoller;
 use alloy::eips::BlockNumberOrTag;
 use alloy::primitives::aliases::U40;
 use alloy::primitives::{Bytes, U256};
-use alloy::rpc::types::{Log, TransactionReceipt};
+use alloy::providers::Provider;
+use alloy::rpc::types::{BlockTransactionsKind, Log, TransactionReceipt};
 use eyre::{eyre, Context, Result};
+use futures_util::future;
+use futures_util::stream::{self, LocalBoxStream};
+use futures_util::{Stream, StreamExt, TryStreamExt};
 use LLMOracleTask::{TaskResponse, TaskValidation};
 use OracleCoordinator::LLMOracleTaskParameters;
 use OracleCoordinator::{getResponsesReturn, getValidationsReturn, requestsReturn, StatusUpdate};
 
+/// A batch of task events from [`DriaOracle::subscribe_to_tasks`].
+#[derive(Debug)]
+pub struct TaskBatch {
+    /// Events of the batch, which may be empty.
+    pub events: Vec<alloy::sol_types::Result<(StatusUpdate, Log)>>,
+    /// The block until which all events are streamed, including the ones of the previous batches,
+    /// if it is known; so that the blocks without any events are known to be processed as well.
+    pub synced_block: Option<u64>,
+}
+
+impl TaskBatch {
+    fn new(
+        events: Vec<alloy::sol_types::Result<(StatusUpdate, Log)>>,
+        synced_block: Option<u64>,
+    ) -> Self {
+        Self {
+            events,
+            synced_block,
+        }
+    }
+}
+
 impl DriaOracle {
     /// Request an oracle task. This is not done by the oracle normally, but we have it added for testing purposes.
     pub async fn request(
@@ -30,17 +55,10 @@ impl DriaOracle {
             numValidations: U40::from(num_vals),
         };
         let req = coordinator.request(string_to_bytes32(protocol)?, input, models, parameters);
-        let tx = req
-            .send()
+        let receipt = self
+            .send_tx("request", req.into_transaction_request())
             .await
-            .map_err(contract_error_report)
             .wrap_err("could not request task")?;
-
-        log::info!("Hash: {:?}", tx.tx_hash());
-        let receipt = tx
-            .with_timeout(self.config.tx_timeout)
-            .get_receipt()
-            .await?;
         Ok(receipt)
     }
 
@@ -90,12 +108,8 @@ impl DriaOracle {
         let coordinator = OracleCoordinator::new(self.addresses.coordinator, &self.provider);
 
         let req = coordinator.respond(task_id, nonce, response, metadata);
-        let tx = req.send().await.map_err(contract_error_report)?;
-
-        log::info!("Hash: {:?}", tx.tx_hash());
-        let receipt = tx
-            .with_timeout(self.config.tx_timeout)
-            .get_receipt()
+        let receipt = self
+            .send_tx("respond", req.into_transaction_request())
             .await?;
         Ok(receipt)
     }
@@ -110,43 +124,230 @@ impl DriaOracle {
         let coordinator = OracleCoordinator::new(self.addresses.coordinator, &self.provider);
 
         let req = coordinator.validate(task_id, nonce, scores, metadata);
-        let tx = req.send().await.map_err(contract_error_report)?;
-
-        log::info!("Hash: {:?}", tx.tx_hash());
-        let receipt = tx
-            .with_timeout(self.config.tx_timeout)
-            .get_receipt()
+        let receipt = self
+            .send_tx("validate", req.into_transaction_request())
             .await?;
         Ok(receipt)
     }
 
-    /// Subscribes to events & processes tasks.
-    pub async fn subscribe_to_tasks(
-        &self,
-    ) -> Result<EventPoller<DriaOracleProviderTransport, StatusUpdate>> {
+    /// Subscribes to task events, and returns a stream of them in batches.
+    ///
+    /// - For WebSocket & IPC connections, an `eth_subscribe` log subscription is used, where each
+    ///   event is a batch on its own, and new blocks are yielded as empty batches.
+    /// - For HTTP connections, or if the subscription fails, the logs are polled with a filter,
+    ///   where each poll is a batch, even if it is empty.
+    /// - With fallback RPCs, the logs are polled by their block ranges instead, as filters
+    ///   live within a single endpoint; see [`DriaOracle::poll_tasks`].
+    ///
+    /// So, the stream yields something every once in a while as long as it is alive, and the batches
+    /// tell the blocks whose events are all streamed, see [`TaskBatch::synced_block`].
+    /// The stream ends when the connection is lost, in which case it should be subscribed again.
+    pub async fn subscribe_to_tasks(&self) -> Result<LocalBoxStream<'static, TaskBatch>> {
+        if !self.config.fallback_rpc_urls.is_empty() {
+            return self.poll_tasks().await;
+        }
+
         let coordinator = OracleCoordinator::new(self.addresses.coordinator, &self.provider);
+        let filter = coordinator.StatusUpdate_filter();
+
+        if self.config.is_pubsub() {
+            match filter.subscribe().await {
+                Ok(subscription) => {
+                    log::debug!("Subscribed to task events.");
+                    let events = subscription
+                        .into_stream()
+                        .map(|event| Some(TaskBatch::new(vec![event], None)));
+                    let Ok(blocks) = self.provider.subscribe_blocks().await else {
+                        log::warn!("Could not subscribe to blocks, only events are streamed.");
+                        return Ok(events.filter_map(future::ready).boxed_local());
+                    };
+
+                    // the stream ends along with the events, even if the blocks continue
+                    let events = events.chain(stream::once(future::ready(None)));
+                    // the events of a block may be streamed after the block itself, but not after the next one
+                    let blocks = blocks.into_stream().map(|header| {
+                        Some(TaskBatch::new(
+                            Vec::new(),
+                            Some(header.number.saturating_sub(1)),
+                        ))
+                    });
+                    return Ok(stream::select(events, blocks)
+                        .take_while(|batch| future::ready(batch.is_some()))
+                        .filter_map(future::ready)
+                        .boxed_local());
+                }
+                Err(e) => log::warn!("Could not subscribe to logs, polling instead: {}", e),
+            }
+        }
 
-        Ok(coordinator.StatusUpdate_filter().watch().await?)
+        let poller = filter.watch().await?;
+        let provider = self.provider.clone();
+        log::debug!("Polling task events.");
+
+        // each poll returns the events until the head at that time, so the head that is seen after
+        // a poll is synced by the next one
+        let polls = poller.poller.into_stream().boxed_local();
+        Ok(stream::unfold((polls, None), move |(mut polls, head)| {
+            let provider = provider.clone();
+            async move {
+                let logs = polls.next().await?;
+                let events = logs
+                    .into_iter()
+                    .map(|log| {
+                        log.log_decode::<StatusUpdate>()
+                            .map(|e| (e.inner.data, log))
+                    })
+                    .collect();
+                let next_head = provider.get_block_number().await.ok();
+
+                Some((TaskBatch::new(events, head), (polls, next_head)))
+            }
+        })
+        .boxed_local())
+    }
+
+    /// Polls the task events from the next block on, by querying the logs of the new blocks at each poll.
+    ///
+    /// Unlike filters, this does not depend on the state of an endpoint, so it keeps working when
+    /// the requests fail over to another endpoint. Each poll is a batch, even if it is empty, and the
+    /// stream ends if a poll fails.
+    async fn poll_tasks(&self) -> Result<LocalBoxStream<'static, TaskBatch>> {
+        let from_block = self.provider.get_block_number().await? + 1;
+        let coordinator = OracleCoordinator::new(self.addresses.coordinator, self.provider.clone());
+        let poll_interval = self.provider.client().poll_interval();
+        let chunk_size = self.config.log_chunk_size.max(1);
+        log::debug!("Polling task events by block ranges.");
+
+        Ok(stream::unfold(from_block, move |from_block| {
+            let coordinator = coordinator.clone();
+            async move {
+                tokio::time::sleep(poll_interval).await;
+                let head = match coordinator.provider().get_block_number().await {
+                    Ok(head) => head,
+                    Err(e) => {
+                        log::error!("Could not poll task events: {}", e);
+                        return None;
+                    }
+                };
+                if head < from_block {
+                    return Some((TaskBatch::new(Vec::new(), None), from_block));
+                }
+
+                let to_block = from_block.saturating_add(chunk_size - 1).min(head);
+                match coordinator
+                    .StatusUpdate_filter()
+                    .from_block(from_block)
+                    .to_block(to_block)
+                    .query()
+                    .await
+                {
+                    Ok(tasks) => Some((
+                        TaskBatch::new(tasks.into_iter().map(Ok).collect(), Some(to_block)),
+                        to_block + 1,
+                    )),
+                    Err(e) => {
+                        log::error!("Could not poll task events: {}", e);
+                        None
+                    }
+                }
+            }
+        })
+        .boxed_local())
     }
 
     /// Get previous tasks within the range of blocks.
+    ///
+    /// The range is queried in chunks, see [`DriaOracle::get_tasks_in_range_chunked`].
     pub async fn get_tasks_in_range(
         &self,
         from_block: impl Into<BlockNumberOrTag>,
         to_block: impl Into<BlockNumberOrTag>,
     ) -> Result<Vec<(StatusUpdate, Log)>> {
-        let coordinator = OracleCoordinator::new(self.addresses.coordinator, &self.provider);
-
-        let tasks = coordinator
-            .StatusUpdate_filter()
-            .from_block(from_block)
-            .to_block(to_block)
-            .query()
+        let tasks = self
+            .get_tasks_in_range_chunked(from_block, to_block)
+            .await?
+            .try_concat()
             .await?;
 
         Ok(tasks)
     }
 
+    /// Get previous tasks within the range of blocks, as a stream of chunks.
+    ///
+    /// The range is split into chunks of `log_chunk_size` blocks, each queried with a separate `eth_getLogs` call
+    /// only when the stream is polled. If the provider rejects a chunk due to its range or result size,
+    /// the chunk size is halved and the query is retried.
+    pub async fn get_tasks_in_range_chunked(
+        &self,
+        from_block: impl Into<BlockNumberOrTag>,
+        to_block: impl Into<BlockNumberOrTag>,
+    ) -> Result<impl Stream<Item = Result<Vec<(StatusUpdate, Log)>>> + '_> {
+        let from_block = self.get_block_number_of(from_block.into()).await?;
+        let to_block = self.get_block_number_of(to_block.into()).await?;
+        let chunk_size = self.config.log_chunk_size.max(1);
+
+        let stream = stream::try_unfold(
+            (from_block, chunk_size),
+            move |(from_block, mut chunk_size)| async move {
+                if from_block > to_block {
+                    return Ok(None);
+                }
+
+                let coordinator =
+                    OracleCoordinator::new(self.addresses.coordinator, &self.provider);
+                loop {
+                    let chunk_end = from_block.saturating_add(chunk_size - 1).min(to_block);
+                    let result = coordinator
+                        .StatusUpdate_filter()
+                        .from_block(from_block)
+                        .to_block(chunk_end)
+                        .query()
+                        .await;
+
+                    match result {
+                        Ok(tasks) => return Ok(Some((tasks, (chunk_end + 1, chunk_size)))),
+                        Err(err) if chunk_size > 1 && is_log_range_error(&err) => {
+                            chunk_size /= 2;
+                            log::debug!(
+                                "Log range {}..{} rejected, retrying with chunk size {}.",
+                                from_block,
+                                chunk_end,
+                                chunk_size
+                            );
+                        }
+                        Err(err) => {
+                            return Err(eyre!(err).wrap_err(format!(
+                                "could not get tasks between blocks {} - {}",
+                                from_block, chunk_end
+                            )))
+                        }
+                    }
+                }
+            },
+        );
+
+        Ok(stream)
+    }
+
+    /// Returns the block number of a given block number or tag.
+    async fn get_block_number_of(&self, block: BlockNumberOrTag) -> Result<u64> {
+        match block {
+            BlockNumberOrTag::Number(block_number) => Ok(block_number),
+            BlockNumberOrTag::Earliest => Ok(0),
+            BlockNumberOrTag::Latest | BlockNumberOrTag::Pending => self
+                .provider
+                .get_block_number()
+                .await
+                .wrap_err("could not get block number"),
+            tag => self
+                .provider
+                .get_block_by_number(tag, BlockTransactionsKind::Hashes)
+                .await?
+                .map(|block| block.header.number)
+                .ok_or_else(|| eyre!("Block {} not found.", tag)),
+        }
+    }
+
     /// Get task info for a given task id.
     pub async fn get_task(
         &self,
@@ -177,6 +378,18 @@ impl DriaOracle {
         Ok(task_id._0)
     }
 
+    /// Returns the generation & validation deviation factors of the coordinator.
+    ///
+    /// Responses with scores that deviate from the mean by more than the factor times
+    /// the standard deviation are not paid.
+    pub async fn get_deviation_factors(&self) -> Result<(u64, u64)> {
+        let coordinator = OracleCoordinator::new(self.addresses.coordinator, &self.provider);
+
+        let generation = coordinator.generationDeviationFactor().call().await?;
+        let validation = coordinator.validationDeviationFactor().call().await?;
+        Ok((generation._0, validation._0))
+    }
+
     /// Get fee details for a given request setting.
     pub async fn get_request_fee(
         &self,
@@ -197,3 +410,42 @@ impl DriaOracle {
         Ok(fees)
     }
 }
+
+/// Returns `true` if the error is due to a log query having too large of a block range,
+/// or resulting in too many logs.
+///
+/// Providers do not have a standard error for this, so we check the error message.
+fn is_log_range_error(error: &impl std::fmt::Display) -> bool {
+    // "limit exceeded" alone is not a pattern, as it is also the message of rate limits
+    const PATTERNS: [&str; 6] = [
+        "block range",
+        "range too large",
+        "range is too large",
+        "too many results",
+        "query returned more than",
+        "response size exceeded",
+    ];
+
+    let message = error.to_string().to_lowercase();
+    PATTERNS.iter().any(|pattern| message.contains(pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_log_range_error() {
+        assert!(is_log_range_error(&"server returned an error response: error code -32602: eth_getLogs block range too large, range: 100001, max: 10000"));
+        assert!(is_log_range_error(
+            &"query returned more than 10000 results"
+        ));
+        assert!(!is_log_range_error(&"execution reverted"));
+
+        // rate limits are not range errors, so the range is not shrunk for them
+        assert!(!is_log_range_error(
+            &"server returned an error response: error code -32005: rate limit exceeded"
+        ));
+        assert!(!is_log_range_error(&"daily request limit exceeded"));
+    }
+}
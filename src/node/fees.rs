@@ -0,0 +1,212 @@
+use super::DriaOracle;
+use crate::configurations::{format_gwei, FeePolicy, PriorityFee};
+use alloy::{
+    eips::BlockNumberOrTag,
+    providers::{
+        utils::{
+            eip1559_default_estimator, Eip1559Estimation, EIP1559_FEE_ESTIMATION_PAST_BLOCKS,
+            EIP1559_FEE_ESTIMATION_REWARD_PERCENTILE,
+        },
+        Provider,
+    },
+};
+use eyre::{eyre, Context, Result};
+
+/// The fees per gas are above the ceiling of the fee policy, so the transaction is not sent.
+///
+/// Tasks that fail with this error are deferred until the fees are lower, instead of being retried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeCeilingExceeded {
+    /// Base fee plus the priority fee per gas, in wei.
+    pub fee_per_gas: u128,
+    /// Ceiling of the fee policy, in wei.
+    pub ceiling: u128,
+}
+
+impl std::fmt::Display for FeeCeilingExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Fee per gas {} gwei is above the ceiling of {} gwei",
+            format_gwei(self.fee_per_gas),
+            format_gwei(self.ceiling)
+        )
+    }
+}
+
+impl std::error::Error for FeeCeilingExceeded {}
+
+impl FeePolicy {
+    /// Applies the policy to the estimated fees w.r.t the given base fee per gas.
+    ///
+    /// Returns an error if the base fee plus the priority fee is above the ceiling,
+    /// otherwise the fees are capped to the maximum fee per gas.
+    pub fn apply(
+        &self,
+        base_fee_per_gas: u128,
+        estimation: Eip1559Estimation,
+    ) -> Result<Eip1559Estimation, FeeCeilingExceeded> {
+        let fee_per_gas = base_fee_per_gas.saturating_add(estimation.max_priority_fee_per_gas);
+        if let Some(ceiling) = self.fee_ceiling {
+            if fee_per_gas > ceiling {
+                return Err(FeeCeilingExceeded {
+                    fee_per_gas,
+                    ceiling,
+                });
+            }
+        }
+
+        let max_fee_per_gas = match self.max_fee_per_gas {
+            Some(max_fee_per_gas) => estimation.max_fee_per_gas.min(max_fee_per_gas),
+            None => estimation.max_fee_per_gas,
+        };
+        Ok(Eip1559Estimation {
+            max_fee_per_gas,
+            max_priority_fee_per_gas: estimation.max_priority_fee_per_gas.min(max_fee_per_gas),
+        })
+    }
+}
+
+impl DriaOracle {
+    /// Estimates the fees per gas of a transaction w.r.t the fee policy, using `eth_feeHistory`.
+    ///
+    /// Returns `None` if the policy is the default one, so that the provider fills the fees itself.
+    /// Returns [`FeeCeilingExceeded`] as the error if the fees are above the ceiling of the policy.
+    pub async fn estimate_fees(&self) -> Result<Option<Eip1559Estimation>> {
+        let policy = &self.config.fee_policy;
+        if policy.is_default() {
+            return Ok(None);
+        }
+
+        let percentile = match policy.priority_fee {
+            PriorityFee::Percentile(percentile) => percentile,
+            _ => EIP1559_FEE_ESTIMATION_REWARD_PERCENTILE,
+        };
+        let history = self
+            .provider
+            .get_fee_history(
+                EIP1559_FEE_ESTIMATION_PAST_BLOCKS,
+                BlockNumberOrTag::Latest,
+                &[percentile],
+            )
+            .await
+            .wrap_err("could not get fee history")?;
+        // the last base fee is of the next block
+        let base_fee_per_gas = history
+            .base_fee_per_gas
+            .last()
+            .copied()
+            .ok_or_else(|| eyre!("Chain does not support EIP-1559 fees."))?;
+        let rewards = history.reward.unwrap_or_default();
+
+        let estimation = match policy.priority_fee {
+            PriorityFee::Estimated => eip1559_default_estimator(base_fee_per_gas, &rewards),
+            PriorityFee::Fixed(priority_fee) => {
+                Self::fees_with_priority(base_fee_per_gas, priority_fee)
+            }
+            PriorityFee::Percentile(_) => {
+                let mut priority_fees = rewards
+                    .iter()
+                    .filter_map(|reward| reward.first().copied())
+                    .collect::<Vec<_>>();
+                priority_fees.sort_unstable();
+                let priority_fee = priority_fees
+                    .get(priority_fees.len() / 2)
+                    .copied()
+                    .unwrap_or_default();
+                Self::fees_with_priority(base_fee_per_gas, priority_fee)
+            }
+        };
+
+        let fees = policy.apply(base_fee_per_gas, estimation)?;
+        if fees.max_fee_per_gas < base_fee_per_gas {
+            log::warn!(
+                "Maximum fee per gas {} gwei is below the base fee {} gwei, the transaction may wait a while.",
+                format_gwei(fees.max_fee_per_gas),
+                format_gwei(base_fee_per_gas)
+            );
+        }
+
+        Ok(Some(fees))
+    }
+
+    /// Ensures that the fees are below the ceiling of the fee policy, if there is one,
+    /// so that the work for a task is not done when its response would not be sent anyways.
+    pub async fn check_fee_ceiling(&self) -> Result<()> {
+        if self.config.fee_policy.fee_ceiling.is_some() {
+            self.estimate_fees().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the gas price that a transaction would pay at most w.r.t the fee policy, i.e. the maximum
+    /// fee per gas from [`Self::estimate_fees`], or `eth_gasPrice` if the policy is the default one.
+    pub async fn estimate_gas_price(&self) -> Result<u128> {
+        match self.estimate_fees().await? {
+            Some(fees) => Ok(fees.max_fee_per_gas),
+            None => self
+                .provider
+                .get_gas_price()
+                .await
+                .wrap_err("could not get gas price"),
+        }
+    }
+
+    /// Fees with the given priority fee, where the maximum fee allows the base fee to double.
+    fn fees_with_priority(base_fee_per_gas: u128, priority_fee: u128) -> Eip1559Estimation {
+        Eip1559Estimation {
+            max_fee_per_gas: base_fee_per_gas
+                .saturating_mul(2)
+                .saturating_add(priority_fee),
+            max_priority_fee_per_gas: priority_fee,
+        }
+    }
+}
+
+/// Returns whether the error is due to the fees being above the ceiling of the fee policy.
+pub fn is_fee_ceiling_error(error: &eyre::Report) -> bool {
+    error
+        .chain()
+        .any(|e| e.downcast_ref::<FeeCeilingExceeded>().is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GWEI: u128 = 1_000_000_000;
+
+    #[test]
+    fn test_fee_policy() {
+        let estimation = Eip1559Estimation {
+            max_fee_per_gas: 50 * GWEI,
+            max_priority_fee_per_gas: 2 * GWEI,
+        };
+
+        // the default policy does not change anything
+        let fees = FeePolicy::default().apply(20 * GWEI, estimation).unwrap();
+        assert_eq!(fees, estimation);
+
+        // fees are capped
+        let policy = FeePolicy {
+            max_fee_per_gas: Some(30 * GWEI),
+            ..Default::default()
+        };
+        let fees = policy.apply(20 * GWEI, estimation).unwrap();
+        assert_eq!(fees.max_fee_per_gas, 30 * GWEI);
+        assert_eq!(fees.max_priority_fee_per_gas, 2 * GWEI);
+
+        // fees above the ceiling are rejected
+        let policy = FeePolicy {
+            fee_ceiling: Some(21 * GWEI),
+            ..Default::default()
+        };
+        let error = policy.apply(20 * GWEI, estimation).unwrap_err();
+        assert_eq!(error.fee_per_gas, 22 * GWEI);
+        assert!(is_fee_ceiling_error(
+            &eyre::Report::new(error).wrap_err("could not respond")
+        ));
+        assert!(policy.apply(19 * GWEI, estimation).is_ok());
+    }
+}
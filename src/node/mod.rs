@@ -1,7 +1,20 @@
 mod coordinator;
+pub use coordinator::TaskBatch;
 mod registry;
 mod token;
 
+mod rpc;
+pub use rpc::FailoverTransport;
+
+mod fees;
+pub use fees::{is_fee_ceiling_error, FeeCeilingExceeded};
+
+mod nonce;
+pub use nonce::NonceManager;
+
+mod tx;
+pub use tx::{is_simulation_revert, SimulationReverted};
+
 #[cfg(feature = "anvil")]
 mod anvil;
 
@@ -13,16 +26,17 @@ use alloy::providers::fillers::{
 use alloy::providers::WalletProvider;
 use alloy::{
     network::{Ethereum, EthereumWallet},
-    primitives::Address,
+    primitives::{Address, B256},
     providers::{Identity, Provider, ProviderBuilder, RootProvider},
-    transports::http::{Client, Http},
+    rpc::{client::RpcClient, types::BlockTransactionsKind},
+    transports::{utils::guess_local_url, BoxTransport},
 };
 use alloy_chains::Chain;
 use eyre::{eyre, Context, Result};
 use std::env;
 
-// TODO: use a better type for these
-type DriaOracleProviderTransport = Http<Client>;
+/// Transport of the provider, boxed so that HTTP, WebSocket and IPC connections are all supported.
+type DriaOracleProviderTransport = BoxTransport;
 type DriaOracleProvider = FillProvider<
     JoinFill<
         JoinFill<
@@ -36,35 +50,47 @@ type DriaOracleProvider = FillProvider<
     Ethereum,
 >;
 
+#[derive(Clone)]
 pub struct DriaOracle {
     pub config: DriaOracleConfig,
+    /// Chain id of the connected chain.
+    pub chain_id: u64,
     /// Contract addresses for the oracle, respects the connected chain.
     pub addresses: ContractAddresses,
     /// Underlying provider type.
     pub provider: DriaOracleProvider,
+    /// Nonces of the transactions sent by the node's wallet, shared with the connected nodes.
+    pub nonces: NonceManager,
 }
 
 impl DriaOracle {
     /// Creates a new oracle node with the given private key and connected to the chain at the given RPC URL.
     ///
-    /// The contract addresses are chosen based on the chain id returned from the provider.
+    /// The RPC URL can be an HTTP, WebSocket (`ws://`, `wss://`) or IPC (`file://`) connection.
+    /// The contract addresses are chosen based on the chain id returned from the provider,
+    /// unless they are given within the config.
     pub async fn new(config: DriaOracleConfig) -> Result<Self> {
-        let provider = ProviderBuilder::new()
-            .with_recommended_fillers()
-            .wallet(config.wallet.clone())
-            .on_http(config.rpc_url.clone());
+        let provider = Self::connect_provider(&config).await?;
 
         // fetch the chain id so that we can use the correct addresses
-        let chain_id_u64 = provider
+        let chain_id = provider
             .get_chain_id()
             .await
             .wrap_err("could not get chain id")?;
-        let chain = Chain::from_id(chain_id_u64);
+        let addresses = match &config.addresses {
+            Some(addresses) => addresses.clone(),
+            None => ADDRESSES
+                .get(&Chain::from_id(chain_id))
+                .cloned()
+                .ok_or_else(|| eyre!("No contract addresses are known for chain {}.", chain_id))?,
+        };
 
         let node = Self {
             config,
-            addresses: ADDRESSES[&chain].clone(),
+            chain_id,
+            addresses,
             provider,
+            nonces: NonceManager::default(),
         };
 
         node.check_contract_sizes().await?;
@@ -73,10 +99,32 @@ impl DriaOracle {
         Ok(node)
     }
 
+    /// Connects to the RPC URL of the given config, with its wallet.
+    ///
+    /// If there are fallback RPC URLs, requests fail over among all of them, see [`FailoverTransport`].
+    pub async fn connect_provider(config: &DriaOracleConfig) -> Result<DriaOracleProvider> {
+        let builder = ProviderBuilder::new()
+            .with_recommended_fillers()
+            .wallet(config.wallet.clone());
+
+        if config.fallback_rpc_urls.is_empty() {
+            return builder
+                .on_builtin(&rpc::connection_string(&config.rpc_url)?)
+                .await
+                .wrap_err("could not connect to RPC");
+        }
+
+        let urls = config.rpc_urls();
+        let is_local = urls.iter().all(|url| guess_local_url(url.as_str()));
+        let transport = FailoverTransport::connect(&urls).await?;
+        Ok(builder.on_client(RpcClient::new(BoxTransport::new(transport), is_local)))
+    }
+
     /// Creates a new node with the given wallet.
     ///
     /// - Provider is cloned and its wallet is mutated.
     /// - Config is cloned and its wallet & address are updated.
+    /// - Nonce manager is shared, so that the nodes with the same wallet use the same nonces.
     pub fn connect(&self, wallet: EthereumWallet) -> Self {
         let mut provider = self.provider.clone();
         *provider.wallet_mut() = wallet.clone();
@@ -84,7 +132,9 @@ impl DriaOracle {
         Self {
             provider,
             config: self.config.clone().with_wallet(wallet),
+            chain_id: self.chain_id,
             addresses: self.addresses.clone(),
+            nonces: self.nonces.clone(),
         }
     }
 
@@ -94,6 +144,15 @@ impl DriaOracle {
         Ok(TokenBalance::new(balance, "ETH".to_string(), None))
     }
 
+    /// Returns the hash of the block with the given number, `None` if there is no such block.
+    pub async fn get_block_hash(&self, block_number: u64) -> Result<Option<B256>> {
+        let block = self
+            .provider
+            .get_block_by_number(block_number.into(), BlockTransactionsKind::Hashes)
+            .await?;
+        Ok(block.map(|block| block.header.hash))
+    }
+
     /// Checks contract sizes to ensure they are deployed.
     ///
     /// Returns an error if any of the contracts are not deployed.
@@ -157,10 +216,28 @@ impl core::fmt::Display for DriaOracle {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Dria Oracle Node v{}\nAddress: {}\nRPC URL: {}",
+            "Dria Oracle Node v{}\nAddress: {}\nChain ID: {}\nRPC URL: {}",
             env!("CARGO_PKG_VERSION"),
             self.address(),
+            self.chain_id,
             self.config.rpc_url,
-        )
+        )?;
+        if !self.config.fallback_rpc_urls.is_empty() {
+            write!(
+                f,
+                "\nFallback RPC URLs: {}",
+                self.config
+                    .fallback_rpc_urls
+                    .iter()
+                    .map(|url| url.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        if !self.config.fee_policy.is_default() {
+            write!(f, "\nFee Policy: {}", self.config.fee_policy)?;
+        }
+
+        Ok(())
     }
 }
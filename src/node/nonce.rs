@@ -0,0 +1,261 @@
+use super::DriaOracle;
+use alloy::{
+    primitives::{Address, TxHash},
+    providers::Provider,
+};
+use eyre::{Context, Result};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+};
+
+/// Allocates the nonces of the transactions sent by the node, so that concurrent transactions
+/// get sequential nonces without waiting for each other, instead of racing for the same one.
+///
+/// Nonces are counted locally while there are transactions being sent, and are synced with the
+/// pending transaction count of the RPC otherwise. Nonces of the transactions that fail before
+/// being broadcast, or that are confirmed to be dropped afterwards, are released and handed out
+/// again before the new ones to fill the gap.
+///
+/// The nonces are kept per sender, and the manager is shared by the nodes that are connected
+/// from one another; so that the nodes with the same wallet on a chain use the same nonces.
+#[derive(Debug, Clone, Default)]
+pub struct NonceManager {
+    states: Arc<Mutex<HashMap<Address, NonceState>>>,
+}
+
+#[derive(Debug, Default)]
+struct NonceState {
+    /// Next nonce to hand out, unless there are released ones.
+    next: u64,
+    /// Nonces that are handed out but not yet broadcast.
+    in_flight: BTreeSet<u64>,
+    /// Nonces that are broadcast but not yet known to be mined, which are never handed out again
+    /// unless their transaction is confirmed to be dropped.
+    broadcast: BTreeSet<u64>,
+    /// Nonces that are below the next one but are not used, e.g. their transaction has failed.
+    released: BTreeSet<u64>,
+    /// Number of nonces that are known to be mined, so that an RPC that is lagging behind, e.g. a
+    /// fallback endpoint, can not cause them to be handed out again.
+    mined: u64,
+}
+
+impl NonceState {
+    /// Returns `true` if no transaction is being sent, so that the nonces should be synced.
+    fn is_idle(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+
+    /// Syncs the nonces with the pending & latest transaction counts of the sender.
+    ///
+    /// Nonces below the latest count are mined, and the ones below the pending count are used already;
+    /// while the ones from the pending count up to the next one are gaps, unless they are being sent or
+    /// are broadcast, as the RPC may not have seen a broadcast transaction yet.
+    fn sync(&mut self, pending: u64, latest: u64) {
+        let latest = latest.max(self.mined);
+        let pending = pending.max(latest);
+        self.broadcast.retain(|nonce| *nonce >= latest);
+        self.released.retain(|nonce| *nonce >= pending);
+        for nonce in pending..self.next {
+            if !self.in_flight.contains(&nonce) && !self.broadcast.contains(&nonce) {
+                self.released.insert(nonce);
+            }
+        }
+        self.next = self.next.max(pending);
+    }
+
+    /// Hands out the lowest released nonce, or the next one.
+    fn take(&mut self) -> u64 {
+        let nonce = self.released.pop_first().unwrap_or_else(|| {
+            self.next += 1;
+            self.next - 1
+        });
+        self.in_flight.insert(nonce);
+        nonce
+    }
+
+    /// Marks the nonce as used by a broadcast transaction.
+    fn sent(&mut self, nonce: u64) {
+        self.in_flight.remove(&nonce);
+        self.broadcast.insert(nonce);
+    }
+
+    /// Marks the nonce as used by a mined transaction.
+    fn mined(&mut self, nonce: u64) {
+        self.broadcast.remove(&nonce);
+        self.mined = self.mined.max(nonce + 1);
+    }
+
+    /// Releases the nonce of a transaction that is not broadcast, so that it is handed out again.
+    fn release(&mut self, nonce: u64) {
+        if self.in_flight.remove(&nonce) {
+            self.released.insert(nonce);
+        }
+    }
+
+    /// Releases the nonce of a broadcast transaction that is confirmed to be dropped.
+    fn dropped(&mut self, nonce: u64) {
+        if self.broadcast.remove(&nonce) {
+            self.released.insert(nonce);
+        }
+    }
+}
+
+impl NonceManager {
+    fn with_state<T>(&self, address: Address, f: impl FnOnce(&mut NonceState) -> T) -> T {
+        let mut states = self.states.lock().expect("nonce lock is poisoned");
+        f(states.entry(address).or_default())
+    }
+
+    /// Marks the nonce as used by a broadcast transaction.
+    pub fn sent(&self, address: Address, nonce: u64) {
+        self.with_state(address, |state| state.sent(nonce));
+    }
+
+    /// Marks the nonce as used by a mined transaction.
+    pub fn mined(&self, address: Address, nonce: u64) {
+        self.with_state(address, |state| state.mined(nonce));
+    }
+
+    /// Releases the nonce of a transaction that has failed before being broadcast.
+    pub fn release(&self, address: Address, nonce: u64) {
+        self.with_state(address, |state| state.release(nonce));
+    }
+
+    /// Releases the nonce of a broadcast transaction that is confirmed to be dropped.
+    pub fn dropped(&self, address: Address, nonce: u64) {
+        self.with_state(address, |state| state.dropped(nonce));
+    }
+}
+
+impl DriaOracle {
+    /// Returns the nonce for the next transaction of the node, see [`NonceManager`].
+    ///
+    /// The nonce must be either marked as sent or released afterwards.
+    pub async fn next_nonce(&self) -> Result<u64> {
+        let address = self.address();
+        if self.nonces.with_state(address, |state| state.is_idle()) {
+            self.sync_nonces().await?;
+        }
+
+        Ok(self.nonces.with_state(address, |state| state.take()))
+    }
+
+    /// Syncs the nonces with the transaction counts of the node, i.e. `eth_getTransactionCount`
+    /// at `pending` & `latest`.
+    pub async fn sync_nonces(&self) -> Result<()> {
+        let address = self.address();
+        let pending = self
+            .provider
+            .get_transaction_count(address)
+            .pending()
+            .await
+            .wrap_err("could not get transaction count")?;
+        let latest = self
+            .provider
+            .get_transaction_count(address)
+            .latest()
+            .await
+            .wrap_err("could not get transaction count")?;
+        self.nonces
+            .with_state(address, |state| state.sync(pending, latest));
+
+        Ok(())
+    }
+
+    /// Settles the nonce of a broadcast transaction whose receipt could not be awaited, e.g. it has timed out.
+    ///
+    /// The nonce is released only if the transaction is confirmed to be dropped, i.e. nothing is mined with
+    /// the nonce and none of the given hashes (the transaction & its replacements) are known by the RPC.
+    pub(super) async fn settle_nonce(&self, nonce: u64, hashes: &[TxHash]) {
+        let address = self.address();
+        match self.provider.get_transaction_count(address).latest().await {
+            Ok(latest) if latest > nonce => {
+                self.nonces.mined(address, nonce);
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log::warn!("Could not get transaction count: {}", e);
+                return;
+            }
+        }
+
+        for hash in hashes {
+            match self.provider.get_transaction_by_hash(*hash).await {
+                Ok(None) => {}
+                Ok(Some(_)) => return,
+                Err(e) => {
+                    log::warn!("Could not get transaction {}: {}", hash, e);
+                    return;
+                }
+            }
+        }
+
+        log::warn!(
+            "Transaction with nonce {} is dropped, its nonce will be used again.",
+            nonce
+        );
+        self.nonces.dropped(address, nonce);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nonce_allocation() {
+        let mut state = NonceState::default();
+        assert!(state.is_idle());
+        state.sync(5, 5);
+
+        // concurrent transactions get sequential nonces
+        assert_eq!(state.take(), 5);
+        assert_eq!(state.take(), 6);
+        assert_eq!(state.take(), 7);
+        assert!(!state.is_idle());
+
+        // the one that fails before being broadcast is handed out again
+        state.sent(5);
+        state.release(6);
+        assert_eq!(state.take(), 6);
+        assert_eq!(state.take(), 8);
+
+        // a lagging RPC that has not seen the broadcast one does not cause it to be handed out again
+        state.sync(5, 5);
+        assert_eq!(state.take(), 9);
+
+        // unless it is confirmed to be dropped
+        state.dropped(5);
+        assert_eq!(state.take(), 5);
+
+        // nor the mined one, even if the RPC has not seen it yet
+        state.sent(5);
+        state.mined(5);
+        state.sync(5, 5);
+        assert_eq!(state.take(), 10);
+        assert!(state.released.is_empty());
+
+        // a sync w/ a higher count skips the nonces used elsewhere
+        for nonce in [6, 7, 8, 9, 10] {
+            state.sent(nonce);
+        }
+        assert!(state.is_idle());
+        state.sync(13, 11);
+        assert_eq!(state.take(), 13);
+        assert!(state.broadcast.is_empty());
+    }
+
+    #[test]
+    fn test_nonce_manager_per_sender() {
+        let manager = NonceManager::default();
+        let shared = manager.clone();
+        let (alice, bob) = (Address::with_last_byte(1), Address::with_last_byte(2));
+
+        // clones share the nonces of a sender, while the senders are independent
+        assert_eq!(manager.with_state(alice, |state| state.take()), 0);
+        assert_eq!(shared.with_state(alice, |state| state.take()), 1);
+        assert_eq!(shared.with_state(bob, |state| state.take()), 0);
+    }
+}
@@ -1,5 +1,5 @@
 use super::{DriaOracle, TokenBalance};
-use crate::{node::contract_error_report, OracleKind, OracleRegistry, ERC20};
+use crate::{OracleKind, OracleRegistry, ERC20};
 use alloy::{primitives::Address, rpc::types::TransactionReceipt};
 use eyre::{eyre, Context, Result};
 
@@ -9,17 +9,10 @@ impl DriaOracle {
         let registry = OracleRegistry::new(self.addresses.registry, &self.provider);
 
         let req = registry.register(kind.into());
-        let tx = req
-            .send()
+        let receipt = self
+            .send_tx("register", req.into_transaction_request())
             .await
-            .map_err(contract_error_report)
             .wrap_err(eyre!("could not register"))?;
-
-        log::info!("Hash: {:?}", tx.tx_hash());
-        let receipt = tx
-            .with_timeout(self.config.tx_timeout)
-            .get_receipt()
-            .await?;
         Ok(receipt)
     }
 
@@ -28,17 +21,10 @@ impl DriaOracle {
         let registry = OracleRegistry::new(self.addresses.registry, &self.provider);
 
         let req = registry.unregister(kind.into());
-        let tx = req
-            .send()
+        let receipt = self
+            .send_tx("unregister", req.into_transaction_request())
             .await
-            .map_err(contract_error_report)
             .wrap_err("could not unregister")?;
-
-        log::info!("Hash: {:?}", tx.tx_hash());
-        let receipt = tx
-            .with_timeout(self.config.tx_timeout)
-            .get_receipt()
-            .await?;
         Ok(receipt)
     }
 
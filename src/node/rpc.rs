@@ -0,0 +1,305 @@
+//! Failover among multiple RPC endpoints.
+
+use crate::metrics;
+use alloy::{
+    primitives::U64,
+    rpc::{
+        client::{BuiltInConnectionString, RpcClient},
+        json_rpc::{RequestPacket, ResponsePacket},
+    },
+    transports::{BoxTransport, TransportError, TransportErrorKind, TransportFut},
+};
+use eyre::{eyre, Context, Result};
+use reqwest::Url;
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex, Weak},
+    task::{Context as TaskContext, Poll},
+    time::{Duration, Instant},
+};
+use tower::Service;
+
+/// Interval of the health probes of the endpoints.
+const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+/// Maximum latency of a probe for an endpoint to be healthy.
+const MAX_LATENCY: Duration = Duration::from_secs(5);
+/// Maximum error rate of the requests for an endpoint to be healthy.
+const MAX_ERROR_RATE: f64 = 0.5;
+/// Maximum number of blocks an endpoint can be behind the others to be healthy.
+const MAX_HEAD_LAG: u64 = 3;
+/// Weight of the latest result within the error rate.
+const ERROR_RATE_WEIGHT: f64 = 0.2;
+
+/// Returns the connection string of an RPC URL, where IPC connections are made with the path itself.
+pub fn connection_string(url: &Url) -> Result<String> {
+    if url.scheme() == "file" {
+        Ok(url
+            .to_file_path()
+            .map_err(|_| eyre!("Invalid IPC path: {}", url))?
+            .display()
+            .to_string())
+    } else {
+        Ok(url.to_string())
+    }
+}
+
+/// Health of an endpoint, w.r.t its probes & the requests made to it.
+#[derive(Debug, Clone, Default)]
+struct EndpointHealth {
+    /// Latency of the latest successful probe.
+    latency: Option<Duration>,
+    /// Latest block number reported by the endpoint.
+    head: Option<u64>,
+    /// Moving average of the failed requests.
+    error_rate: f64,
+    /// Whether the latest request or probe has failed.
+    failing: bool,
+}
+
+impl EndpointHealth {
+    fn record(&mut self, ok: bool) {
+        let error = if ok { 0.0 } else { 1.0 };
+        self.error_rate = self.error_rate * (1.0 - ERROR_RATE_WEIGHT) + error * ERROR_RATE_WEIGHT;
+        self.failing = !ok;
+    }
+
+    /// An endpoint is healthy if it is responding quickly with few errors,
+    /// and it is not lagging behind the highest known head.
+    ///
+    /// Endpoints that are not probed yet are considered healthy.
+    fn is_healthy(&self, max_head: Option<u64>) -> bool {
+        let lag = match (max_head, self.head) {
+            (Some(max_head), Some(head)) => max_head.saturating_sub(head),
+            _ => 0,
+        };
+
+        !self.failing
+            && self.error_rate < MAX_ERROR_RATE
+            && lag <= MAX_HEAD_LAG
+            && !matches!(self.latency, Some(latency) if latency > MAX_LATENCY)
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    /// Host of the endpoint for the logs, so that the API keys within URLs are not logged.
+    name: String,
+    transport: BoxTransport,
+}
+
+#[derive(Debug, Default)]
+struct FailoverState {
+    health: Vec<EndpointHealth>,
+    /// Index of the endpoint that is used at the moment, for logging the switches.
+    current: usize,
+}
+
+/// A transport over multiple RPC endpoints, ordered by their priorities.
+///
+/// Each request goes to the healthiest endpoint with the highest priority, and moves on to the
+/// next one if it fails at the transport level, e.g. the connection is lost or rate-limited.
+/// Error responses of the RPC, such as reverts, are returned as is.
+///
+/// The endpoints are probed periodically to measure their latency & head, and requests update
+/// their error rates; see [`EndpointHealth::is_healthy`].
+#[derive(Debug, Clone)]
+pub struct FailoverTransport {
+    endpoints: Arc<Vec<Endpoint>>,
+    state: Arc<Mutex<FailoverState>>,
+}
+
+impl FailoverTransport {
+    /// Connects to the given RPC URLs, where the first one has the highest priority.
+    ///
+    /// The endpoints are probed in the background as long as the transport is alive.
+    pub async fn connect(urls: &[Url]) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(eyre!("No RPC URLs provided."));
+        }
+
+        // endpoints that are down at the moment are skipped, as long as one of them is up
+        let mut endpoints = Vec::new();
+        for (idx, url) in urls.iter().enumerate() {
+            let name = format!("#{} ({})", idx, url.host_str().unwrap_or(url.scheme()));
+            let connection = BuiltInConnectionString::from_str(&connection_string(url)?)
+                .wrap_err_with(|| format!("invalid RPC URL #{}", idx))?;
+            match connection.connect_boxed().await {
+                Ok(transport) => endpoints.push(Endpoint { name, transport }),
+                Err(e) => log::error!("Could not connect to RPC {}, skipping it: {}", name, e),
+            }
+        }
+        if endpoints.is_empty() {
+            return Err(eyre!("Could not connect to any of the RPC URLs."));
+        }
+
+        let transport = Self {
+            state: Arc::new(Mutex::new(FailoverState {
+                health: vec![EndpointHealth::default(); endpoints.len()],
+                ..Default::default()
+            })),
+            endpoints: Arc::new(endpoints),
+        };
+        transport.probe().await;
+
+        let endpoints = Arc::downgrade(&transport.endpoints);
+        let state = Arc::downgrade(&transport.state);
+        tokio::spawn(Self::probe_periodically(endpoints, state));
+
+        Ok(transport)
+    }
+
+    /// Returns the indices of the endpoints in the order they should be tried.
+    ///
+    /// Healthy endpoints come first by their priorities, and the rest by their error rates.
+    fn ranked(&self) -> Vec<usize> {
+        let state = self.state.lock().expect("rpc lock is poisoned");
+        let max_head = state.health.iter().filter_map(|h| h.head).max();
+
+        let (mut healthy, mut unhealthy): (Vec<usize>, Vec<usize>) =
+            (0..state.health.len()).partition(|idx| state.health[*idx].is_healthy(max_head));
+        unhealthy.sort_by(|a, b| {
+            state.health[*a]
+                .error_rate
+                .total_cmp(&state.health[*b].error_rate)
+        });
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    /// Records the result of a request or probe to an endpoint.
+    fn record(&self, idx: usize, ok: bool) {
+        let mut state = self.state.lock().expect("rpc lock is poisoned");
+        state.health[idx].record(ok);
+        if ok && state.current != idx {
+            log::warn!(
+                "Switched from RPC {} to {}.",
+                self.endpoints[state.current].name,
+                self.endpoints[idx].name
+            );
+            state.current = idx;
+        }
+    }
+
+    /// Probes all endpoints for their latency & head.
+    async fn probe(&self) {
+        let probes = self.endpoints.iter().map(|endpoint| async move {
+            let client = RpcClient::new(endpoint.transport.clone(), false);
+            let started_at = Instant::now();
+            let result = tokio::time::timeout(
+                MAX_LATENCY,
+                client.request_noparams::<U64>("eth_blockNumber"),
+            )
+            .await;
+            (started_at.elapsed(), result)
+        });
+        let results = futures_util::future::join_all(probes).await;
+
+        let mut state = self.state.lock().expect("rpc lock is poisoned");
+        for (idx, (latency, result)) in results.into_iter().enumerate() {
+            let health = &mut state.health[idx];
+            match result {
+                Ok(Ok(head)) => {
+                    health.latency = Some(latency);
+                    health.head = Some(head.to());
+                    health.record(true);
+                }
+                Ok(Err(e)) => {
+                    log::warn!("RPC {} probe failed: {}", self.endpoints[idx].name, e);
+                    health.record(false);
+                }
+                Err(_) => {
+                    log::warn!("RPC {} probe timed out.", self.endpoints[idx].name);
+                    health.latency = Some(latency);
+                    health.record(false);
+                }
+            }
+        }
+
+        let max_head = state.health.iter().filter_map(|h| h.head).max();
+        for (endpoint, health) in self.endpoints.iter().zip(&state.health) {
+            let healthy = health.is_healthy(max_head);
+            metrics::RPC_ENDPOINT_HEALTHY
+                .with_label_values(&[&endpoint.name])
+                .set(if healthy { 1.0 } else { 0.0 });
+        }
+    }
+
+    async fn probe_periodically(endpoints: Weak<Vec<Endpoint>>, state: Weak<Mutex<FailoverState>>) {
+        let mut interval = tokio::time::interval(PROBE_INTERVAL);
+        interval.tick().await; // the first tick is immediate, and the endpoints are just probed
+        loop {
+            interval.tick().await;
+            let (Some(endpoints), Some(state)) = (endpoints.upgrade(), state.upgrade()) else {
+                break;
+            };
+            Self { endpoints, state }.probe().await;
+        }
+    }
+
+    /// Sends the request to the endpoints in order, until one of them responds.
+    async fn request(self, request: RequestPacket) -> Result<ResponsePacket, TransportError> {
+        let mut last_error = None;
+        for idx in self.ranked() {
+            let endpoint = &self.endpoints[idx];
+            let mut transport = endpoint.transport.clone();
+            match transport.call(request.clone()).await {
+                Ok(response) => {
+                    self.record(idx, true);
+                    return Ok(response);
+                }
+                Err(e) => {
+                    log::warn!("RPC {} request failed: {}", endpoint.name, e);
+                    self.record(idx, false);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| TransportErrorKind::custom_str("no RPC endpoints")))
+    }
+}
+
+impl Service<RequestPacket> for FailoverTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        Box::pin(self.clone().request(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_health() {
+        let mut health = EndpointHealth::default();
+        assert!(health.is_healthy(None));
+
+        health.head = Some(100);
+        health.latency = Some(Duration::from_millis(100));
+        assert!(health.is_healthy(Some(103)));
+        assert!(!health.is_healthy(Some(104)));
+
+        health.record(false);
+        assert!(!health.is_healthy(Some(100)));
+        health.record(true);
+        assert!(health.is_healthy(Some(100)));
+
+        for _ in 0..5 {
+            health.record(false);
+        }
+        health.record(true);
+        assert!(!health.is_healthy(Some(100)));
+
+        health.error_rate = 0.0;
+        health.latency = Some(MAX_LATENCY * 2);
+        assert!(!health.is_healthy(Some(100)));
+    }
+}
@@ -30,12 +30,8 @@ impl DriaOracle {
         let token = ERC20::new(self.addresses.token, &self.provider);
 
         let req = token.transferFrom(from, to, amount);
-        let tx = req.send().await.map_err(contract_error_report)?;
-
-        log::info!("Hash: {:?}", tx.tx_hash());
-        let receipt = tx
-            .with_timeout(self.config.tx_timeout)
-            .get_receipt()
+        let receipt = self
+            .send_tx("transfer_from", req.into_transaction_request())
             .await?;
         Ok(receipt)
     }
@@ -44,17 +40,10 @@ impl DriaOracle {
         let token = ERC20::new(self.addresses.token, &self.provider);
 
         let req = token.approve(spender, amount);
-        let tx = req
-            .send()
+        let receipt = self
+            .send_tx("approve", req.into_transaction_request())
             .await
-            .map_err(contract_error_report)
             .wrap_err("could not approve tokens")?;
-
-        log::info!("Hash: {:?}", tx.tx_hash());
-        let receipt = tx
-            .with_timeout(self.config.tx_timeout)
-            .get_receipt()
-            .await?;
         Ok(receipt)
     }
 
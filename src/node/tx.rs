@@ -0,0 +1,389 @@
+use super::DriaOracle;
+use crate::{
+    configurations::ReplacementPolicy, contracts::contract_error_report, metrics, telemetry,
+};
+use alloy::{
+    consensus::Transaction,
+    network::TransactionBuilder,
+    primitives::{TxHash, U256},
+    providers::Provider,
+    rpc::{
+        json_rpc::ErrorPayload,
+        types::{TransactionReceipt, TransactionRequest},
+    },
+};
+use eyre::{eyre, Context, Result};
+use std::time::Instant;
+use tracing::Instrument;
+
+/// Gas limit of a plain transfer, used by the cancellations.
+const TRANSFER_GAS: u64 = 21_000;
+/// Number of consecutive times that a transaction is not found by the RPC, before it is given up on as dropped.
+const MAX_MISSES: usize = 3;
+
+/// The transaction reverts when it is simulated, so it is not sent.
+///
+/// The reason is the decoded revert of the contracts, see [`contract_error_report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulationReverted {
+    /// Method of the transaction, e.g. `respond`.
+    pub method: String,
+    /// Reason of the revert.
+    pub reason: String,
+}
+
+impl std::fmt::Display for SimulationReverted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Transaction {} reverted in simulation: {}",
+            self.method, self.reason
+        )
+    }
+}
+
+impl std::error::Error for SimulationReverted {}
+
+/// Returns whether the error is due to a transaction that reverted in simulation.
+pub fn is_simulation_revert(error: &eyre::Report) -> bool {
+    error
+        .chain()
+        .any(|e| e.downcast_ref::<SimulationReverted>().is_some())
+}
+
+/// Returns whether the error response of the RPC is a revert, i.e. it has the code 3,
+/// carries revert data or says so; other error responses such as rate limits are transient.
+fn is_revert_response(payload: &ErrorPayload) -> bool {
+    payload.code == 3
+        || payload.as_revert_data().is_some()
+        || payload
+            .message
+            .to_lowercase()
+            .contains("execution reverted")
+}
+
+impl DriaOracle {
+    /// Sends a transaction w.r.t the fee policy, and waits for its receipt.
+    ///
+    /// Unless disabled by the config, the transaction is simulated with `eth_call` first, and
+    /// [`SimulationReverted`] is returned as the error if it reverts, without sending it.
+    ///
+    /// If there is a replacement policy, the transaction is replaced with bumped fees when it is
+    /// not mined in time, see [`ReplacementPolicy`]; and the receipt of whichever transaction
+    /// is mined is returned.
+    ///
+    /// The nonce is given by the [`NonceManager`](super::NonceManager), so that concurrent transactions
+    /// do not wait for each other. The transaction is sent & awaited on a task of its own from then on,
+    /// so that its nonce is released or settled even if this future is dropped, e.g. the task is cancelled;
+    /// in which case the transaction is still sent & awaited, but its receipt is not returned.
+    ///
+    /// The transaction is recorded to the metrics with the given method, and its hash is
+    /// recorded to the span of the current task. Reverts are reported as contract errors.
+    pub async fn send_tx(
+        &self,
+        method: &str,
+        mut tx: TransactionRequest,
+    ) -> Result<TransactionReceipt> {
+        if self.config.simulate {
+            self.simulate_tx(method, &tx).await?;
+        }
+        if let Some(fees) = self.estimate_fees().await? {
+            tx.set_max_fee_per_gas(fees.max_fee_per_gas);
+            tx.set_max_priority_fee_per_gas(fees.max_priority_fee_per_gas);
+        }
+
+        let node = self.clone();
+        let method = method.to_string();
+        tokio::spawn(
+            async move { node.send_tx_with_nonce(&method, tx).await }
+                .instrument(tracing::Span::current()),
+        )
+        .await
+        .wrap_err("could not send transaction")?
+    }
+
+    /// Sends the transaction with the next nonce, and waits for its receipt; see [`Self::send_tx`].
+    ///
+    /// The nonce is either marked as mined, released or settled before this returns.
+    async fn send_tx_with_nonce(
+        &self,
+        method: &str,
+        mut tx: TransactionRequest,
+    ) -> Result<TransactionReceipt> {
+        let address = self.address();
+        let nonce = self.next_nonce().await?;
+        tx.set_nonce(nonce);
+        let pending = match self.provider.send_transaction(tx.clone()).await {
+            Ok(pending) => {
+                self.nonces.sent(address, nonce);
+                pending
+            }
+            Err(e) => {
+                // the nonce is not used if the transaction is not broadcast, otherwise the sync will skip it
+                self.nonces.release(address, nonce);
+                if let Err(err) = self.sync_nonces().await {
+                    log::warn!("Could not sync nonces: {:?}", err);
+                }
+                return Err(contract_error_report(e.into()));
+            }
+        };
+        let sent_at = std::time::Instant::now();
+
+        telemetry::record_tx_hash(pending.tx_hash());
+        log::info!("Hash: {:?}", pending.tx_hash());
+        let mut hashes = vec![*pending.tx_hash()];
+        let result = match &self.config.replacement {
+            Some(replacement) => self.watch_tx(tx, &mut hashes, replacement).await,
+            None => pending
+                .with_timeout(self.config.tx_timeout)
+                .get_receipt()
+                .await
+                .map_err(eyre::Report::from),
+        };
+        let receipt = match result {
+            Ok(receipt) => {
+                self.nonces.mined(address, nonce);
+                receipt
+            }
+            Err(e) => {
+                self.settle_nonce(nonce, &hashes).await;
+                return Err(e);
+            }
+        };
+        metrics::record_receipt(self.chain_id, method, &receipt, sent_at.elapsed());
+
+        Ok(receipt)
+    }
+
+    /// Simulates the transaction with `eth_call` on the latest block, from the node's address.
+    ///
+    /// Returns [`SimulationReverted`] as the error if the call reverts, see [`is_revert_response`];
+    /// other errors, e.g. rate limits, are returned as they are so that they can be retried.
+    pub async fn simulate_tx(&self, method: &str, tx: &TransactionRequest) -> Result<()> {
+        let mut call = tx.clone();
+        if call.from.is_none() {
+            call.set_from(self.address());
+        }
+
+        match self.provider.call(&call).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.as_error_resp().is_some_and(is_revert_response) => {
+                Err(SimulationReverted {
+                    method: method.to_string(),
+                    reason: contract_error_report(e.into()).to_string(),
+                }
+                .into())
+            }
+            Err(e) => Err(e).wrap_err("could not simulate transaction"),
+        }
+    }
+
+    /// Waits for the receipt of a sent transaction, and replaces it with bumped fees each time it is not
+    /// mined within the timeout of the policy, until the replacements are exhausted. Then, the transaction
+    /// is cancelled if the policy says so. Neither is sent above the maximum fee per gas of the fee policy.
+    ///
+    /// The given hashes start with the sent transaction, and its replacements are added to them.
+    ///
+    /// Returns the receipt of whichever of the transactions is mined, or an error if the cancellation
+    /// is mined, the transaction timeout is over, nothing is mined within the timeout of the policy
+    /// after the last transaction is sent, or the latest transaction can not be found for [`MAX_MISSES`]
+    /// times in a row, e.g. it is dropped from the mempool.
+    async fn watch_tx(
+        &self,
+        tx: TransactionRequest,
+        hashes: &mut Vec<TxHash>,
+        policy: &ReplacementPolicy,
+    ) -> Result<TransactionReceipt> {
+        let started_at = Instant::now();
+        let tx_hash = hashes[0];
+        let mut replacements = 0;
+        let mut misses = 0;
+        let mut cancellation = None;
+        // nothing more is sent once set, so the next wait is the last one
+        let mut exhausted = false;
+
+        loop {
+            if let Some(receipt) = self.wait_for_receipt(hashes, policy).await {
+                if Some(receipt.transaction_hash) == cancellation {
+                    return Err(eyre!(
+                        "Transaction {} is cancelled by {}.",
+                        tx_hash,
+                        receipt.transaction_hash
+                    ));
+                }
+                if receipt.transaction_hash != tx_hash {
+                    log::info!(
+                        "Transaction {} is replaced by {}, which is mined.",
+                        tx_hash,
+                        receipt.transaction_hash
+                    );
+                }
+                return Ok(receipt);
+            }
+
+            if let Some(tx_timeout) = self.config.tx_timeout {
+                if started_at.elapsed() >= tx_timeout {
+                    return Err(eyre!(
+                        "Transaction {} is not mined, timed out after {:?}.",
+                        tx_hash,
+                        tx_timeout
+                    ));
+                }
+            }
+            if exhausted {
+                return Err(eyre!(
+                    "Transaction {} is not mined after {} replacements.",
+                    tx_hash,
+                    replacements
+                ));
+            }
+
+            // replace the latest transaction, as the previous ones are replaced by it already
+            let latest = *hashes.last().expect("there is a transaction");
+            let sent = match self.provider.get_transaction_by_hash(latest).await {
+                Ok(Some(sent)) => {
+                    misses = 0;
+                    Some(sent)
+                }
+                Ok(None) => {
+                    log::warn!("Transaction {} is not known by the RPC, waiting.", latest);
+                    misses += 1;
+                    None
+                }
+                Err(e) => {
+                    log::warn!("Could not get transaction {}: {}", latest, e);
+                    misses += 1;
+                    None
+                }
+            };
+            let Some(sent) = sent else {
+                if misses >= MAX_MISSES {
+                    return Err(eyre!(
+                        "Transaction {} is not found after {} attempts, it may be dropped.",
+                        latest,
+                        misses
+                    ));
+                }
+                continue;
+            };
+            let max_fee_per_gas = policy.bump(sent.max_fee_per_gas());
+            let max_priority_fee_per_gas = policy.bump(
+                sent.max_priority_fee_per_gas()
+                    .unwrap_or_else(|| sent.max_fee_per_gas()),
+            );
+            let within_cap = self
+                .config
+                .fee_policy
+                .max_fee_per_gas
+                .map_or(true, |cap| max_fee_per_gas <= cap);
+
+            if !within_cap {
+                // a cancellation must bump the fees as well, so it is not sent either
+                log::warn!(
+                    "Transaction {} can not be replaced above the maximum fee per gas.",
+                    latest
+                );
+                exhausted = true;
+                continue;
+            }
+
+            let replace = replacements < policy.max_replacements;
+            let replacement = if replace {
+                tx.clone()
+            } else if policy.cancel {
+                TransactionRequest::default()
+                    .with_to(self.address())
+                    .with_value(U256::ZERO)
+                    .with_gas_limit(TRANSFER_GAS)
+            } else {
+                exhausted = true;
+                continue;
+            };
+            let replacement = replacement
+                .with_nonce(sent.nonce())
+                .with_max_fee_per_gas(max_fee_per_gas)
+                .with_max_priority_fee_per_gas(max_priority_fee_per_gas.min(max_fee_per_gas));
+
+            // the transaction may be mined in the meantime, so failures are only logged
+            match self.provider.send_transaction(replacement).await {
+                Ok(pending) => {
+                    let hash = *pending.tx_hash();
+                    if replace {
+                        replacements += 1;
+                        log::warn!(
+                            "Transaction {} is not mined in time, replaced by {} (attempt {}).",
+                            latest,
+                            hash,
+                            replacements
+                        );
+                    } else {
+                        log::warn!(
+                            "Transaction {} is not mined in time, cancelling it with {}.",
+                            latest,
+                            hash
+                        );
+                        cancellation = Some(hash);
+                        exhausted = true;
+                    }
+                    telemetry::record_tx_hash(&hash);
+                    hashes.push(hash);
+                }
+                Err(e) => log::warn!("Could not replace transaction {}: {}", latest, e),
+            }
+        }
+    }
+
+    /// Waits for the receipt of any of the given transactions within the timeout of the policy.
+    ///
+    /// Errors are only logged, as the transactions may still be mined.
+    async fn wait_for_receipt(
+        &self,
+        hashes: &[TxHash],
+        policy: &ReplacementPolicy,
+    ) -> Option<TransactionReceipt> {
+        let deadline = Instant::now() + policy.timeout;
+        let interval = self.provider.client().poll_interval();
+        loop {
+            for hash in hashes {
+                match self.provider.get_transaction_receipt(*hash).await {
+                    Ok(Some(receipt)) => return Some(receipt),
+                    Ok(None) => {}
+                    Err(e) => log::warn!("Could not get receipt of {}: {}", hash, e),
+                }
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            tokio::time::sleep(interval.min(deadline - now)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_revert_response() {
+        let payload = |json: &str| serde_json::from_str::<ErrorPayload>(json).unwrap();
+
+        assert!(is_revert_response(&payload(
+            r#"{"code":3,"message":"execution reverted","data":"0x"}"#
+        )));
+        assert!(is_revert_response(&payload(
+            r#"{"code":-32000,"message":"execution reverted: not registered"}"#
+        )));
+
+        assert!(!is_revert_response(&payload(
+            r#"{"code":-32005,"message":"limit exceeded"}"#
+        )));
+        assert!(!is_revert_response(&payload(
+            r#"{"code":-32000,"message":"header not found"}"#
+        )));
+        assert!(!is_revert_response(&payload(
+            r#"{"code":-32603,"message":"internal error"}"#
+        )));
+    }
+}
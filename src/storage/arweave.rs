@@ -1,8 +1,9 @@
 use crate::bytes_to_string;
 
 use super::traits::IsExternalStorage;
-use alloy::primitives::Bytes;
+use alloy::primitives::{keccak256, Bytes};
 use async_trait::async_trait;
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
 use bundlr_sdk::{currency::arweave::ArweaveBuilder, tags::Tag, BundlrBuilder};
 use eyre::{eyre, Context, Result};
 use reqwest::{Client, Url};
@@ -30,6 +31,8 @@ pub struct ArweaveStorage {
     /// - If the data exceeds this limit, it will be uploaded to Arweave.
     /// - Otherwise, it will be stored as is.
     byte_limit: usize,
+    /// Whether to skip the uploads, see [`ArweaveStorage::with_dry_run`].
+    dry_run: bool,
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
@@ -46,9 +49,20 @@ impl ArweaveStorage {
             base_url: Url::parse(base_url).wrap_err("could not parse base URL")?,
             client: Client::new(),
             byte_limit,
+            dry_run: false,
         })
     }
 
+    /// Change the dry-run mode, where large values are not uploaded but
+    /// a placeholder key is returned instead.
+    ///
+    /// The actual key can not be known without uploading, as it is derived from a
+    /// randomized signature; so the placeholder is derived from the hash of the value.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
     /// Parses a given bytes input to a string,
     /// and if it is a storage key identifier it automatically downloads the data from Arweave.
     pub async fn parse_downloadable(input_bytes: &Bytes) -> Result<String> {
@@ -100,7 +114,18 @@ impl ArweaveStorage {
                 value_size,
                 self.byte_limit
             );
-            let key = self.put(value.clone()).await?;
+            let key = if self.dry_run {
+                let key = ArweaveKey {
+                    arweave: BASE64_URL_SAFE_NO_PAD.encode(keccak256(&value)),
+                };
+                log::info!(
+                    "Dry run, not uploading & using placeholder key: {}",
+                    key.arweave
+                );
+                key
+            } else {
+                self.put(value.clone()).await?
+            };
             let key_str = serde_json::to_string(&key).wrap_err("could not serialize key")?;
             Ok(key_str.into())
         } else {
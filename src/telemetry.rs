@@ -0,0 +1,232 @@
+use crate::{
+    contracts::{bytes32_to_string, OracleCoordinator::StatusUpdate},
+    TaskStatus,
+};
+use alloy::primitives::TxHash;
+use dkn_workflows::Model;
+use eyre::{eyre, Context, Result};
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use std::str::FromStr;
+use tracing::{field, level_filters::LevelFilter, Span};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// Format of the log lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines, where the fields of the task are prefixed to each line.
+    #[default]
+    Text,
+    /// A JSON object per line, where the fields of the task are under `span`.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(eyre!(
+                "Invalid log format: {}, expected text or json",
+                value
+            )),
+        }
+    }
+}
+
+/// Keeps the trace exporter alive, and flushes the remaining traces when dropped.
+pub struct TelemetryGuard {
+    provider: Option<TracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Could not flush traces: {}", e);
+            }
+        }
+    }
+}
+
+/// Sets up the logs of the node with the given format, along with an OTLP trace exporter if `otlp` is set.
+///
+/// Only the logs of this crate & workflows are enabled at the given level by default, and
+/// `RUST_LOG` directives are applied on top. The OTLP exporter is configured with the
+/// standard `OTEL_EXPORTER_OTLP_*` variables, e.g. `OTEL_EXPORTER_OTLP_ENDPOINT`.
+///
+/// Logs of the `log` crate are forwarded as well, so they carry the fields of their task.
+pub fn init(format: LogFormat, level: LevelFilter, otlp: bool) -> Result<TelemetryGuard> {
+    let mut directives = format!("off,dria_oracle={0},dkn_workflows={0}", level);
+    if let Ok(rust_log) = std::env::var("RUST_LOG") {
+        directives = format!("{},{}", directives, rust_log);
+    }
+    let filter = EnvFilter::builder().parse_lossy(directives);
+
+    let logs = match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    };
+
+    let provider = otlp
+        .then(|| {
+            SpanExporter::builder()
+                .with_tonic()
+                .build()
+                .map(tracer_provider)
+                .wrap_err("could not create OTLP exporter")
+        })
+        .transpose()?;
+    let traces = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+    });
+
+    tracing_subscriber::registry()
+        .with(logs)
+        .with(traces)
+        .with(filter)
+        .try_init()
+        .wrap_err("could not set up logging")?;
+
+    Ok(TelemetryGuard { provider })
+}
+
+/// Creates the provider that exports the traces of this node in batches with the given exporter.
+fn tracer_provider(exporter: SpanExporter) -> TracerProvider {
+    TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            env!("CARGO_PKG_NAME"),
+        )]))
+        .build()
+}
+
+/// Creates the span of a task on the given chain, where the model & transaction hash
+/// are empty at first, see [`record_model`] & [`record_tx_hash`].
+pub fn task_span(chain_id: u64, event: &StatusUpdate) -> Span {
+    let kind = match TaskStatus::try_from(event.statusAfter) {
+        Ok(TaskStatus::PendingGeneration) => "generator",
+        Ok(TaskStatus::PendingValidation) => "validator",
+        _ => "none",
+    };
+
+    tracing::info_span!(
+        "task",
+        chain = chain_id,
+        task_id = %event.taskId,
+        protocol = %bytes32_to_string(&event.protocol).unwrap_or_default(),
+        kind,
+        model = field::Empty,
+        tx_hash = field::Empty,
+    )
+}
+
+/// Records the model of the task within the current span.
+pub fn record_model(model: &Model) {
+    Span::current().record("model", field::display(model));
+}
+
+/// Records the transaction hash of the response within the current span.
+pub fn record_tx_hash(tx_hash: &TxHash) {
+    Span::current().record("tx_hash", field::display(tx_hash));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_format() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("Text".parse::<LogFormat>().unwrap(), LogFormat::Text);
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+
+    /// Exports a task span to a stub OTLP/HTTP receiver, and checks that its fields arrive as attributes.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_task_span_export() -> Result<()> {
+        use crate::contracts::string_to_bytes32;
+        use alloy::primitives::U256;
+        use axum::{body::Bytes, Router};
+        use opentelemetry_otlp::WithExportConfig;
+        use opentelemetry_proto::tonic::{
+            collector::trace::v1::ExportTraceServiceRequest, common::v1::any_value::Value,
+        };
+        use prost::Message;
+        use std::collections::HashMap;
+        use tokio::sync::mpsc;
+
+        // stub receiver that forwards the bodies of the export requests
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let app = Router::new().fallback(move |body: Bytes| async move {
+            sender.send(body).unwrap();
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("http://{}/v1/traces", addr))
+            .build()?;
+        let provider = tracer_provider(exporter);
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME"))),
+        );
+
+        let event = StatusUpdate {
+            taskId: U256::from(1234),
+            protocol: string_to_bytes32("test/0.1.0".to_string())?,
+            statusBefore: TaskStatus::None.into(),
+            statusAfter: TaskStatus::PendingGeneration.into(),
+        };
+        let tx_hash = TxHash::with_last_byte(1);
+        tracing::subscriber::with_default(subscriber, || {
+            task_span(84532, &event).in_scope(|| {
+                record_model(&Model::GPT4oMini);
+                record_tx_hash(&tx_hash);
+            });
+        });
+
+        // shutting down flushes the batch, and it blocks until the export is done
+        tokio::task::spawn_blocking(move || provider.shutdown()).await??;
+
+        let body = tokio::time::timeout(std::time::Duration::from_secs(5), receiver.recv())
+            .await?
+            .ok_or_else(|| eyre!("receiver is closed"))?;
+        let request = ExportTraceServiceRequest::decode(body)?;
+        let span = request
+            .resource_spans
+            .iter()
+            .flat_map(|resource| &resource.scope_spans)
+            .flat_map(|scope| &scope.spans)
+            .find(|span| span.name == "task")
+            .ok_or_else(|| eyre!("task span is not exported"))?;
+        let attributes = span
+            .attributes
+            .iter()
+            .filter_map(|kv| match kv.value.as_ref()?.value.as_ref()? {
+                Value::StringValue(value) => Some((kv.key.as_str(), value.clone())),
+                Value::IntValue(value) => Some((kv.key.as_str(), value.to_string())),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        assert_eq!(attributes["chain"], "84532");
+        assert_eq!(attributes["task_id"], "1234");
+        assert_eq!(attributes["protocol"], "test/0.1.0");
+        assert_eq!(attributes["kind"], "generator");
+        assert_eq!(attributes["model"], Model::GPT4oMini.to_string());
+        assert_eq!(attributes["tx_hash"], tx_hash.to_string());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+//! Using two local Anvil instances as the RPC and its fallback,
+//!
+//! 1. Sends a transaction, which goes through the first instance
+//! 2. Kills the first instance
+//! 3. Reads through the second instance, and the nonce of the first transaction
+//!    is not handed out again although the second instance has not seen it
+
+use alloy::{
+    network::TransactionBuilder,
+    node_bindings::Anvil,
+    primitives::{Address, U256},
+    providers::{Provider, ProviderBuilder},
+    rpc::types::TransactionRequest,
+};
+use dria_oracle::{ContractAddresses, DriaOracle, DriaOracleConfig, NonceManager};
+use eyre::Result;
+
+#[tokio::test]
+async fn test_rpc_failover() -> Result<()> {
+    let primary = Anvil::new().try_spawn()?;
+    let fallback = Anvil::new().try_spawn()?;
+    let fallback_provider = ProviderBuilder::new().on_http(fallback.endpoint_url());
+
+    // the contracts are not deployed on these instances, and they are not used either
+    let config = DriaOracleConfig::new_local()
+        .with_rpc_url(primary.endpoint_url())
+        .with_fallback_rpc_urls(vec![fallback.endpoint_url()]);
+    let provider = DriaOracle::connect_provider(&config).await?;
+    let node = DriaOracle {
+        chain_id: provider.get_chain_id().await?,
+        addresses: ContractAddresses {
+            token: Address::ZERO,
+            registry: Address::ZERO,
+            coordinator: Address::ZERO,
+        },
+        config,
+        provider,
+        nonces: NonceManager::default(),
+    };
+    let tx = TransactionRequest::default()
+        .with_to(Address::with_last_byte(0x42))
+        .with_value(U256::from(1));
+
+    // the first transaction goes through the primary
+    let receipt = node.send_tx("transfer", tx).await?;
+    assert!(receipt.status());
+    assert_eq!(
+        fallback_provider
+            .get_transaction_count(node.address())
+            .await?,
+        0
+    );
+
+    // the primary goes down, so the reads go through the fallback
+    drop(primary);
+    assert_eq!(
+        node.provider.get_block_number().await?,
+        fallback_provider.get_block_number().await?
+    );
+
+    // the fallback has not seen the first transaction, but its nonce is not handed out again
+    assert_eq!(node.next_nonce().await?, 1);
+
+    Ok(())
+}
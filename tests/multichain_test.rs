@@ -0,0 +1,71 @@
+//! Using two local Anvil instances that fork the chain at `RPC_URL` with different chain ids,
+//!
+//! 1. Connects to the first one, where the addresses are chosen by its chain id
+//! 2. Connects to the second one, where the addresses must be given explicitly
+//! 3. Requests a task on the second chain, which is only seen by its node
+
+use alloy::{
+    eips::BlockNumberOrTag, node_bindings::Anvil, primitives::utils::parse_ether,
+    providers::Provider,
+};
+use dkn_workflows::Model;
+use dria_oracle::{DriaOracle, DriaOracleConfig, WETH};
+use eyre::Result;
+
+#[tokio::test]
+async fn test_multiple_chains() -> Result<()> {
+    dotenvy::dotenv().unwrap();
+
+    let config = DriaOracleConfig::new_from_env()?.with_fallback_rpc_urls(Vec::new());
+    let first = Anvil::new().fork(config.rpc_url.to_string()).try_spawn()?;
+    let second = Anvil::new()
+        .fork(config.rpc_url.to_string())
+        .chain_id(31338)
+        .try_spawn()?;
+
+    // the first chain is known, so its addresses are chosen by its chain id
+    let first_node = DriaOracle::new(config.clone().with_rpc_url(first.endpoint_url())).await?;
+    assert_ne!(first_node.chain_id, 31338);
+    let first_block = first_node.provider.get_block_number().await?;
+
+    // the second chain is not known, so its addresses must be given
+    let second_config = config.with_rpc_url(second.endpoint_url());
+    assert!(DriaOracle::new(second_config.clone()).await.is_err());
+    let second_node =
+        DriaOracle::new(second_config.with_addresses(Some(first_node.addresses.clone()))).await?;
+    assert_eq!(second_node.chain_id, 31338);
+    assert_eq!(second_node.addresses, first_node.addresses);
+
+    // a task requested on the second chain is only seen there
+    let requester = second_node.connect(second_node.anvil_funded_wallet(None).await?);
+    let token = WETH::new(requester.addresses.token, &requester.provider);
+    let _ = token
+        .deposit()
+        .value(parse_ether("100")?)
+        .send()
+        .await?
+        .get_receipt()
+        .await?;
+    let second_block = second_node.provider.get_block_number().await?;
+    requester
+        .request_task(
+            "What is the result of 2 + 2?",
+            vec![Model::GPT4oMini],
+            1,
+            1,
+            1,
+            format!("test/{}", env!("CARGO_PKG_VERSION")),
+        )
+        .await?;
+
+    let second_tasks = second_node
+        .get_tasks_in_range(second_block, BlockNumberOrTag::Latest)
+        .await?;
+    assert_eq!(second_tasks.len(), 1);
+    let first_tasks = first_node
+        .get_tasks_in_range(first_block, BlockNumberOrTag::Latest)
+        .await?;
+    assert!(first_tasks.is_empty());
+
+    Ok(())
+}
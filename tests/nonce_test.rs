@@ -0,0 +1,89 @@
+//! Using the forked blockchain,
+//!
+//! 1. Sends several transactions at once from the same wallet, which get sequential nonces
+//! 2. Sends a transaction that fails before being broadcast, whose nonce is handed out again
+//! 3. Drops a transaction future after it is broadcast, and the next transaction is still mined
+
+use alloy::{
+    consensus::Transaction,
+    network::TransactionBuilder,
+    primitives::{Address, U256},
+    providers::{ext::AnvilApi, Provider},
+    rpc::types::TransactionRequest,
+};
+use dria_oracle::{DriaOracle, DriaOracleConfig};
+use eyre::Result;
+use futures_util::future::join_all;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_concurrent_nonces() -> Result<()> {
+    dotenvy::dotenv().unwrap();
+
+    // without simulation, so that the failing transaction reaches the nonce manager
+    let config = DriaOracleConfig::new_from_env()?.with_simulation(false);
+    let (node, _anvil) = DriaOracle::anvil_new(config).await?;
+    let sender = node.connect(node.anvil_funded_wallet(None).await?);
+    let tx = TransactionRequest::default()
+        .with_to(Address::with_last_byte(0x42))
+        .with_value(U256::from(1));
+
+    // concurrent transactions do not race for the same nonce
+    let receipts = join_all((0..3).map(|_| sender.send_tx("transfer", tx.clone())))
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+    let mut nonces = Vec::new();
+    for receipt in receipts {
+        assert!(receipt.status());
+        let sent = node
+            .provider
+            .get_transaction_by_hash(receipt.transaction_hash)
+            .await?
+            .expect("transaction should be mined");
+        nonces.push(sent.nonce());
+    }
+    nonces.sort_unstable();
+    assert_eq!(nonces, vec![0, 1, 2]);
+
+    // a transaction that can not be sent does not leave a gap behind
+    let too_much = tx.clone().with_value(U256::MAX);
+    assert!(sender.send_tx("transfer", too_much).await.is_err());
+    let receipt = sender.send_tx("transfer", tx.clone()).await?;
+    let sent = node
+        .provider
+        .get_transaction_by_hash(receipt.transaction_hash)
+        .await?
+        .expect("transaction should be mined");
+    assert_eq!(sent.nonce(), 3);
+
+    // a transaction whose future is dropped halfway, e.g. the task is cancelled, is still sent & awaited
+    node.provider.anvil_set_auto_mine(false).await?;
+    let dropped = tokio::time::timeout(
+        Duration::from_secs(1),
+        sender.send_tx("transfer", tx.clone()),
+    )
+    .await;
+    assert!(dropped.is_err());
+    assert_eq!(
+        node.provider
+            .get_transaction_count(sender.address())
+            .pending()
+            .await?,
+        5
+    );
+    node.provider.anvil_mine(Some(U256::from(1)), None).await?;
+    node.provider.anvil_set_auto_mine(true).await?;
+
+    // so its nonce is not left behind, and the next one is mined
+    let receipt = sender.send_tx("transfer", tx).await?;
+    assert!(receipt.status());
+    let sent = node
+        .provider
+        .get_transaction_by_hash(receipt.transaction_hash)
+        .await?
+        .expect("transaction should be mined");
+    assert_eq!(sent.nonce(), 5);
+
+    Ok(())
+}
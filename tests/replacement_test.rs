@@ -0,0 +1,97 @@
+//! Using the forked blockchain with automine disabled, where transactions are stuck until a block is mined,
+//!
+//! 1. Sends a transaction, which is replaced with bumped fees after the timeout
+//! 2. Mines a block, where the replacement is mined instead of the original
+//! 3. Sends another transaction without replacements left, which is cancelled
+//! 4. Sends another transaction that is neither replaced nor cancelled, which fails after the timeout
+
+use alloy::{
+    consensus::Transaction,
+    network::TransactionBuilder,
+    primitives::{Address, U256},
+    providers::{ext::AnvilApi, Provider},
+    rpc::types::TransactionRequest,
+};
+use dria_oracle::{DriaOracle, DriaOracleConfig, FeePolicy, PriorityFee, ReplacementPolicy};
+use eyre::Result;
+use std::time::Duration;
+
+const GWEI: u128 = 1_000_000_000;
+
+#[tokio::test]
+async fn test_stuck_tx_replacement() -> Result<()> {
+    dotenvy::dotenv().unwrap();
+
+    let policy = ReplacementPolicy {
+        timeout: Duration::from_secs(2),
+        fee_bump_percent: 20,
+        max_replacements: 1,
+        cancel: true,
+    };
+    let config = DriaOracleConfig::new_from_env()?
+        .with_fee_policy(FeePolicy {
+            priority_fee: PriorityFee::Fixed(GWEI),
+            ..Default::default()
+        })
+        .with_replacement(Some(policy.clone()));
+    let (node, _anvil) = DriaOracle::anvil_new(config).await?;
+    let mut sender = node.connect(node.anvil_funded_wallet(None).await?);
+    node.provider.anvil_set_auto_mine(false).await?;
+
+    let tx = TransactionRequest::default()
+        .with_to(Address::with_last_byte(0x42))
+        .with_value(U256::from(1));
+    let mine_later = |secs| {
+        let provider = node.provider.clone();
+        async move {
+            tokio::time::sleep(Duration::from_secs(secs)).await;
+            provider.anvil_mine(Some(U256::from(1)), None).await
+        }
+    };
+
+    // the transaction is replaced before the block is mined, so the replacement is mined
+    let (receipt, mined) = tokio::join!(sender.send_tx("transfer", tx.clone()), mine_later(3));
+    mined?;
+    let receipt = receipt?;
+    assert!(receipt.status());
+    let mined_tx = node
+        .provider
+        .get_transaction_by_hash(receipt.transaction_hash)
+        .await?
+        .expect("transaction should be mined");
+    assert_eq!(mined_tx.nonce(), 0);
+    assert_eq!(mined_tx.max_priority_fee_per_gas(), Some(policy.bump(GWEI)));
+    assert_eq!(
+        node.provider
+            .get_transaction_count(sender.address())
+            .await?,
+        1
+    );
+
+    // without replacements left, the transaction is cancelled by a transfer to self
+    sender.config = sender.config.with_replacement(Some(ReplacementPolicy {
+        max_replacements: 0,
+        ..policy
+    }));
+    let (result, mined) = tokio::join!(sender.send_tx("transfer", tx.clone()), mine_later(3));
+    mined?;
+    let error = result.unwrap_err();
+    assert!(error.to_string().contains("cancelled"));
+    assert_eq!(
+        node.provider
+            .get_transaction_count(sender.address())
+            .await?,
+        2
+    );
+
+    // without a cancellation either, it is given up on after the last wait instead of waiting forever
+    sender.config = sender.config.with_replacement(Some(ReplacementPolicy {
+        max_replacements: 0,
+        cancel: false,
+        ..policy
+    }));
+    let error = sender.send_tx("transfer", tx).await.unwrap_err();
+    assert!(error.to_string().contains("not mined"));
+
+    Ok(())
+}
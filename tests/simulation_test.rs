@@ -0,0 +1,44 @@
+//! Using the forked blockchain,
+//!
+//! 1. Responds to a task that does not exist, which reverts in simulation
+//! 2. Checks that the revert is decoded, and that nothing is sent
+
+use alloy::{
+    primitives::{Bytes, U256},
+    providers::Provider,
+};
+use dria_oracle::{DriaOracle, DriaOracleConfig, SimulationReverted};
+use eyre::Result;
+
+#[tokio::test]
+async fn test_simulation_revert() -> Result<()> {
+    dotenvy::dotenv().unwrap();
+
+    let config = DriaOracleConfig::new_from_env()?;
+    let (node, _anvil) = DriaOracle::anvil_new(config).await?;
+    let oracle = node.connect(node.anvil_funded_wallet(None).await?);
+
+    let error = oracle
+        .respond_generation(U256::MAX, Bytes::new(), Bytes::new(), U256::ZERO)
+        .await
+        .unwrap_err();
+    let revert = error
+        .downcast_ref::<SimulationReverted>()
+        .expect("should revert in simulation");
+    assert_eq!(revert.method, "respond");
+    // the oracle is neither registered nor is there such a task, either is decoded
+    assert!(
+        revert.reason.contains("Invalid status for task")
+            || revert.reason.contains("Not registered")
+    );
+
+    // the transaction is not sent at all
+    assert_eq!(
+        node.provider
+            .get_transaction_count(oracle.address())
+            .await?,
+        0
+    );
+
+    Ok(())
+}
@@ -6,7 +6,7 @@ use alloy::{
 use dkn_workflows::{DriaWorkflowsConfig, Model};
 use dria_oracle::{
     bytes_to_string, handle_request, string_to_bytes, DriaOracle, DriaOracleConfig, OracleKind,
-    TaskStatus, WETH,
+    ProtocolFilter, TaskStatus, WETH,
 };
 use eyre::Result;
 
@@ -101,10 +101,18 @@ async fn test_swan() -> Result<()> {
     let task_id = event.taskId;
     assert_eq!(event.statusBefore, TaskStatus::None as u8);
     assert_eq!(event.statusAfter, TaskStatus::PendingGeneration as u8);
-    let generation_receipt =
-        handle_request(&generator, &[OracleKind::Generator], &workflows, event)
-            .await?
-            .unwrap();
+    let generation_receipt = handle_request(
+        &generator,
+        &[OracleKind::Generator],
+        &workflows,
+        &ProtocolFilter::default(),
+        None,
+        None,
+        None,
+        event,
+    )
+    .await?
+    .unwrap();
 
     // handle validation by reading the latest event
     let tasks = node
@@ -118,10 +126,18 @@ async fn test_swan() -> Result<()> {
     assert_eq!(event.taskId, task_id);
     assert_eq!(event.statusBefore, TaskStatus::PendingGeneration as u8);
     assert_eq!(event.statusAfter, TaskStatus::PendingValidation as u8);
-    let validation_receipt =
-        handle_request(&validator, &[OracleKind::Validator], &workflows, event)
-            .await?
-            .unwrap();
+    let validation_receipt = handle_request(
+        &validator,
+        &[OracleKind::Validator],
+        &workflows,
+        &ProtocolFilter::default(),
+        None,
+        None,
+        None,
+        event,
+    )
+    .await?
+    .unwrap();
 
     let tasks = node
         .get_tasks_in_range(